@@ -0,0 +1,502 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::components::Ai;
+use crate::direction::Direction;
+
+// What an enemy is up to. Every enemy starts at its post, the point it spawned at.
+//
+// - Idle: stands still, patrolling enemies wait here at each end of their walk.
+// - Patrol: walks back and forth around its post.
+// - Notice: has just seen the player and stops for a moment before giving chase.
+// - Chase: runs at the player until it is close enough to attack, the player gets too far
+//   away or it gets too far from its post. It keeps chasing around corners.
+// - Attack: winds up, then lunges the way it is facing.
+// - Flee: runs away from the player while its health is low.
+// - Return: walks back to its post, ignoring the player on the way.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AiState {
+    Idle,
+    Patrol,
+    Notice,
+    Chase,
+    Attack,
+    Flee,
+    Return,
+}
+
+// how far left and right of the post to walk, in pixels
+#[derive(Deserialize, Clone, Copy)]
+pub struct PatrolParams {
+    pub left: f32,
+    pub right: f32,
+}
+
+// Per enemy type, set in the prefab's `ai`. Distances are in pixels, speeds in pixels per
+// second and times in milliseconds.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct AiParams {
+    // None stands guard at the post instead
+    pub patrol: Option<PatrolParams>,
+    // patrolling and returning
+    pub speed: f32,
+    // chasing and fleeing
+    pub chase_speed: f32,
+    // notices the player this close
+    pub sight_radius: f32,
+    // stops chasing once the player is this far away
+    pub lose_sight_radius: f32,
+    // stops chasing this far from its post
+    pub leash_distance: f32,
+    pub notice_ms: u64,
+    // the pause at each end of a patrol
+    pub idle_ms: u64,
+    // starts an attack this close to the player
+    pub attack_range: f32,
+    pub windup_ms: u64,
+    pub lunge_ms: u64,
+    pub lunge_speed: f32,
+    // how long it chases before it can attack (again)
+    pub attack_cooldown_ms: u64,
+    // runs away at or below this fraction of its health, 0 never runs
+    pub flee_health: f32,
+}
+
+impl Default for AiParams {
+    fn default() -> AiParams {
+        AiParams {
+            patrol: None,
+            speed: 90.0,
+            chase_speed: 150.0,
+            sight_radius: 256.0,
+            lose_sight_radius: 384.0,
+            leash_distance: 512.0,
+            notice_ms: 400,
+            idle_ms: 1000,
+            attack_range: 64.0,
+            windup_ms: 300,
+            lunge_ms: 250,
+            lunge_speed: 360.0,
+            attack_cooldown_ms: 600,
+            flee_health: 0.0,
+        }
+    }
+}
+
+// close enough to the post to count as back
+const ARRIVE_DISTANCE: f32 = 4.0;
+
+// What an enemy knows about the world this tick. Positions are top left corners. `target`
+// is where the enemy would have to be to stand centred on the player, so distances work
+// out between centres whatever size the two are.
+pub struct Senses {
+    pub position: Vec2<f32>,
+    // None while the player is dead
+    pub target: Option<Vec2<f32>>,
+    // nothing solid between it and the player, it only notices the player it can see
+    pub in_sight: bool,
+    // where to walk next to get to `target` and back to the post without hitting a wall,
+    // None if there is no way there (or it wasn't needed this tick)
+    pub toward_target: Option<Vec2<f32>>,
+    pub toward_post: Option<Vec2<f32>>,
+    // walked into a wall sideways last tick
+    pub blocked: bool,
+    // fraction of health left, 1 for enemies without health
+    pub health: f32,
+}
+
+impl Ai {
+    pub fn new(params: AiParams, post: Vec2<f32>) -> Ai {
+        Ai {
+            state: if params.patrol.is_some() { AiState::Patrol } else { AiState::Idle },
+            time_in_state: Duration::from_secs(0),
+            post,
+            facing: Direction::Left,
+            params,
+        }
+    }
+
+    pub fn enter(&mut self, state: AiState) {
+        self.state = state;
+        self.time_in_state = Duration::from_secs(0);
+    }
+
+    fn face(&mut self, vector: Vec2<f32>) {
+        if let Some(direction) = Direction::from_vector(vector) {
+            self.facing = direction;
+        }
+    }
+}
+
+fn millis(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+// A velocity towards `point` that doesn't overshoot it this tick.
+fn walk_towards(position: Vec2<f32>, point: Vec2<f32>, speed: f32, dt: Duration) -> Vec2<f32> {
+    let vector = point - position;
+    let distance = vector.magnitude();
+
+    if distance <= f32::EPSILON {
+        return Vec2::zero();
+    }
+
+    vector / distance * speed.min(distance / dt.as_secs_f32().max(f32::EPSILON))
+}
+
+// Runs one tick of an enemy's state machine and returns the velocity it wants. Doesn't
+// touch the world, so it can be driven by hand.
+pub fn think(ai: &mut Ai, senses: &Senses, dt: Duration) -> Vec2<f32> {
+    let params = ai.params;
+    ai.time_in_state += dt;
+
+    let to_target = senses.target.map(|target| target - senses.position);
+    let distance = to_target.map(|vector| vector.magnitude());
+    let sees = senses.in_sight && distance.is_some_and(|distance| distance <= params.sight_radius);
+    let to_post = ai.post - senses.position;
+
+    let hurt_enough = params.flee_health > 0.0 && senses.health <= params.flee_health;
+    if hurt_enough && sees && ai.state != AiState::Flee && ai.state != AiState::Return {
+        ai.enter(AiState::Flee);
+    }
+
+    match ai.state {
+        AiState::Idle => {
+            if sees {
+                ai.enter(AiState::Notice);
+            } else if params.patrol.is_some() && ai.time_in_state >= millis(params.idle_ms) {
+                ai.enter(AiState::Patrol);
+            }
+
+            Vec2::zero()
+        },
+        AiState::Patrol => {
+            let patrol = match params.patrol {
+                Some(patrol) => patrol,
+                None => {
+                    ai.enter(AiState::Idle);
+                    return Vec2::zero();
+                },
+            };
+
+            if sees {
+                ai.enter(AiState::Notice);
+                return Vec2::zero();
+            }
+
+            // patrols are sideways, whatever way it was facing before
+            ai.facing = if ai.facing.horizontal() == Direction::Right { Direction::Right } else { Direction::Left };
+
+            // turn around instead of walking into a wall
+            if senses.blocked {
+                ai.facing = ai.facing.opposite();
+            }
+
+            // at either end it turns and waits a moment
+            let x = senses.position.x;
+            if x < ai.post.x - patrol.left && ai.facing == Direction::Left {
+                ai.facing = Direction::Right;
+                ai.enter(AiState::Idle);
+                return Vec2::zero();
+            } else if x > ai.post.x + patrol.right && ai.facing == Direction::Right {
+                ai.facing = Direction::Left;
+                ai.enter(AiState::Idle);
+                return Vec2::zero();
+            }
+
+            ai.facing.to_vector() * params.speed
+        },
+        AiState::Notice => {
+            if let Some(vector) = to_target {
+                ai.face(vector);
+            }
+
+            if ai.time_in_state >= millis(params.notice_ms) {
+                if distance.is_some_and(|distance| distance <= params.lose_sight_radius) {
+                    ai.enter(AiState::Chase);
+                } else {
+                    ai.enter(AiState::Return);
+                }
+            }
+
+            Vec2::zero()
+        },
+        AiState::Chase => {
+            let (vector, distance, step) = match (to_target, distance, senses.toward_target) {
+                (Some(vector), Some(distance), Some(step)) => (vector, distance, step),
+                // the player is dead or somewhere it can't get to
+                _ => {
+                    ai.enter(AiState::Return);
+                    return Vec2::zero();
+                },
+            };
+
+            if distance > params.lose_sight_radius || to_post.magnitude() > params.leash_distance {
+                ai.enter(AiState::Return);
+                return Vec2::zero();
+            }
+
+            ai.face(vector);
+
+            if distance <= params.attack_range && ai.time_in_state >= millis(params.attack_cooldown_ms) {
+                ai.enter(AiState::Attack);
+                return Vec2::zero();
+            }
+
+            walk_towards(senses.position, step, params.chase_speed, dt)
+        },
+        AiState::Attack => {
+            let windup = millis(params.windup_ms);
+
+            if ai.time_in_state < windup {
+                if let Some(vector) = to_target {
+                    ai.face(vector);
+                }
+
+                Vec2::zero()
+            } else if ai.time_in_state < windup + millis(params.lunge_ms) {
+                ai.facing.to_vector() * params.lunge_speed
+            } else {
+                ai.enter(AiState::Chase);
+                Vec2::zero()
+            }
+        },
+        AiState::Flee => {
+            match (to_target, distance) {
+                (Some(vector), Some(distance)) if distance <= params.lose_sight_radius && distance > 0.0 => {
+                    ai.face(-vector);
+                    -vector / distance * params.chase_speed
+                },
+                _ => {
+                    ai.enter(AiState::Return);
+                    Vec2::zero()
+                },
+            }
+        },
+        AiState::Return => {
+            let distance = to_post.magnitude();
+
+            if distance <= ARRIVE_DISTANCE {
+                ai.enter(if params.patrol.is_some() { AiState::Patrol } else { AiState::Idle });
+                return Vec2::zero();
+            }
+
+            // straight back if there's no way round, it may get unstuck on the way
+            let step = senses.toward_post.unwrap_or(ai.post);
+            ai.face(step - senses.position);
+
+            walk_towards(senses.position, step, params.speed, dt)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestep::tick_duration;
+
+    // an enemy at `position` with a clear view of a player standing at `target` and a
+    // straight way to it
+    fn senses(position: Vec2<f32>, target: Vec2<f32>) -> Senses {
+        Senses {
+            position,
+            target: Some(target),
+            in_sight: true,
+            toward_target: Some(target),
+            toward_post: None,
+            blocked: false,
+            health: 1.0,
+        }
+    }
+
+    fn patrolling() -> AiParams {
+        AiParams {
+            patrol: Some(PatrolParams { left: 100.0, right: 100.0 }),
+            ..AiParams::default()
+        }
+    }
+
+    // thinks with the same senses until the state changes, at most `ticks` times
+    fn think_until_change(ai: &mut Ai, senses: &Senses, ticks: usize) -> AiState {
+        let state = ai.state;
+
+        for _ in 0..ticks {
+            think(ai, senses, tick_duration());
+            if ai.state != state {
+                break;
+            }
+        }
+
+        ai.state
+    }
+
+    #[test]
+    fn notices_and_chases_the_player_it_can_see() {
+        for params in [AiParams::default(), patrolling()].iter() {
+            let mut ai = Ai::new(*params, Vec2::zero());
+            let start = ai.state;
+
+            // too far away, then behind a wall
+            think(&mut ai, &senses(Vec2::zero(), Vec2::new(300.0, 0.0)), tick_duration());
+            assert_eq!(ai.state, start);
+
+            let hidden = Senses {
+                in_sight: false,
+                ..senses(Vec2::zero(), Vec2::new(100.0, 0.0))
+            };
+            think(&mut ai, &hidden, tick_duration());
+            assert_eq!(ai.state, start);
+
+            let seen = senses(Vec2::zero(), Vec2::new(100.0, 0.0));
+            assert_eq!(think(&mut ai, &seen, tick_duration()), Vec2::zero());
+            assert_eq!(ai.state, AiState::Notice);
+
+            // it stops for `notice_ms`, 400ms or 24 ticks, turned to the player, then runs at it
+            assert_eq!(think_until_change(&mut ai, &seen, 30), AiState::Chase);
+            assert_eq!(ai.facing, Direction::Right);
+            assert_eq!(ai.time_in_state, Duration::from_secs(0));
+
+            let velocity = think(&mut ai, &seen, tick_duration());
+            assert_eq!(velocity, Vec2::new(params.chase_speed, 0.0));
+        }
+    }
+
+    #[test]
+    fn returns_past_the_leash() {
+        let mut ai = Ai::new(AiParams::default(), Vec2::zero());
+        ai.enter(AiState::Chase);
+
+        // still within the 512 pixel leash
+        think(&mut ai, &senses(Vec2::new(500.0, 0.0), Vec2::new(600.0, 0.0)), tick_duration());
+        assert_eq!(ai.state, AiState::Chase);
+
+        think(&mut ai, &senses(Vec2::new(520.0, 0.0), Vec2::new(620.0, 0.0)), tick_duration());
+        assert_eq!(ai.state, AiState::Return);
+
+        // and walks back to its post, ignoring the player, until it is there
+        let back = senses(Vec2::new(520.0, 0.0), Vec2::new(500.0, 0.0));
+        let velocity = think(&mut ai, &back, tick_duration());
+        assert_eq!(velocity, Vec2::new(-ai.params.speed, 0.0));
+        assert_eq!(ai.state, AiState::Return);
+
+        think(&mut ai, &senses(Vec2::new(2.0, 0.0), Vec2::new(100.0, 0.0)), tick_duration());
+        assert_eq!(ai.state, AiState::Idle);
+    }
+
+    #[test]
+    fn returns_once_the_player_is_out_of_reach() {
+        let mut ai = Ai::new(AiParams::default(), Vec2::zero());
+        ai.enter(AiState::Chase);
+
+        // further than `lose_sight_radius`
+        think(&mut ai, &senses(Vec2::zero(), Vec2::new(400.0, 0.0)), tick_duration());
+        assert_eq!(ai.state, AiState::Return);
+
+        // or with no way to get there
+        ai.enter(AiState::Chase);
+        let cut_off = Senses {
+            toward_target: None,
+            ..senses(Vec2::zero(), Vec2::new(100.0, 0.0))
+        };
+        think(&mut ai, &cut_off, tick_duration());
+        assert_eq!(ai.state, AiState::Return);
+    }
+
+    #[test]
+    fn flees_below_its_health_threshold() {
+        let params = AiParams {
+            flee_health: 0.5,
+            ..AiParams::default()
+        };
+        let mut ai = Ai::new(params, Vec2::zero());
+        ai.enter(AiState::Chase);
+
+        let healthy = Senses {
+            health: 0.75,
+            ..senses(Vec2::zero(), Vec2::new(100.0, 0.0))
+        };
+        think(&mut ai, &healthy, tick_duration());
+        assert_eq!(ai.state, AiState::Chase);
+
+        // at the threshold it turns tail, straight away from the player
+        let hurt = Senses {
+            health: 0.5,
+            ..healthy
+        };
+        let velocity = think(&mut ai, &hurt, tick_duration());
+        assert_eq!(ai.state, AiState::Flee);
+        assert_eq!(velocity, Vec2::new(-params.chase_speed, 0.0));
+        assert_eq!(ai.facing, Direction::Left);
+
+        // until the player is far enough away
+        think(&mut ai, &Senses { health: 0.5, ..senses(Vec2::zero(), Vec2::new(400.0, 0.0)) }, tick_duration());
+        assert_eq!(ai.state, AiState::Return);
+
+        // enemies that never flee keep fighting
+        let mut brave = Ai::new(AiParams::default(), Vec2::zero());
+        brave.enter(AiState::Chase);
+        think(&mut brave, &Senses { health: 0.1, ..senses(Vec2::zero(), Vec2::new(100.0, 0.0)) }, tick_duration());
+        assert_eq!(brave.state, AiState::Chase);
+    }
+
+    #[test]
+    fn attacks_within_range() {
+        let params = AiParams::default();
+        let mut ai = Ai::new(params, Vec2::zero());
+        let close = senses(Vec2::zero(), Vec2::new(-50.0, 0.0));
+
+        // it has to have chased for `attack_cooldown_ms` first
+        ai.enter(AiState::Chase);
+        think(&mut ai, &close, tick_duration());
+        assert_eq!(ai.state, AiState::Chase);
+
+        // out of range it keeps running
+        ai.time_in_state = Duration::from_millis(params.attack_cooldown_ms);
+        think(&mut ai, &senses(Vec2::zero(), Vec2::new(-100.0, 0.0)), tick_duration());
+        assert_eq!(ai.state, AiState::Chase);
+
+        assert_eq!(think(&mut ai, &close, tick_duration()), Vec2::zero());
+        assert_eq!(ai.state, AiState::Attack);
+
+        // winds up in place, lunges at the player, then goes back to chasing
+        assert_eq!(think(&mut ai, &close, tick_duration()), Vec2::zero());
+        ai.time_in_state = Duration::from_millis(params.windup_ms);
+        assert_eq!(think(&mut ai, &close, tick_duration()), Vec2::new(-params.lunge_speed, 0.0));
+        ai.time_in_state = Duration::from_millis(params.windup_ms + params.lunge_ms);
+        think(&mut ai, &close, tick_duration());
+        assert_eq!(ai.state, AiState::Chase);
+    }
+
+    #[test]
+    fn patrols_turn_round_when_blocked() {
+        let mut ai = Ai::new(patrolling(), Vec2::zero());
+        let nobody = Senses {
+            target: None,
+            toward_target: None,
+            ..senses(Vec2::zero(), Vec2::zero())
+        };
+
+        assert_eq!(think(&mut ai, &nobody, tick_duration()), Vec2::new(-ai.params.speed, 0.0));
+
+        let blocked = Senses {
+            blocked: true,
+            ..nobody
+        };
+        assert_eq!(think(&mut ai, &blocked, tick_duration()), Vec2::new(ai.params.speed, 0.0));
+        assert_eq!(ai.facing, Direction::Right);
+        assert_eq!(ai.state, AiState::Patrol);
+
+        // and at the end of its walk it turns and waits
+        let end = Senses {
+            position: Vec2::new(101.0, 0.0),
+            ..nobody
+        };
+        assert_eq!(think(&mut ai, &end, tick_duration()), Vec2::zero());
+        assert_eq!(ai.state, AiState::Idle);
+        assert_eq!(ai.facing, Direction::Left);
+        assert_eq!(think_until_change(&mut ai, &end, 70), AiState::Patrol);
+    }
+}
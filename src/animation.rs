@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::direction::Direction;
+use crate::geometry::Rectangle;
+use crate::spritesheet::{Slice, SpriteSheet};
+use crate::util::{asset_path, load_asset};
+
+// What an entity is doing, as far as its sprite is concerned.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    Die,
+}
+
+impl AnimationState {
+    pub const ALL: [AnimationState; 5] = [
+        AnimationState::Idle,
+        AnimationState::Walk,
+        AnimationState::Attack,
+        AnimationState::Hurt,
+        AnimationState::Die,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AnimationState::Idle => "idle",
+            AnimationState::Walk => "walk",
+            AnimationState::Attack => "attack",
+            AnimationState::Hurt => "hurt",
+            AnimationState::Die => "die",
+        }
+    }
+
+    // one-shot states play their clip once, the rest loop
+    pub fn is_one_shot(self) -> bool {
+        matches!(self, AnimationState::Attack | AnimationState::Hurt | AnimationState::Die)
+    }
+}
+
+fn default_frame_ms() -> u64 {
+    100
+}
+
+// `count` frames of `width` x `height` side by side, starting at `x`, `y`
+#[derive(Deserialize)]
+struct RowDef {
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    width: f32,
+    height: f32,
+    count: usize,
+}
+
+#[derive(Deserialize)]
+struct ClipDef {
+    texture: String,
+    // either a list of [x, y, width, height] regions or a row of equal frames
+    #[serde(default)]
+    frames: Vec<[f32; 4]>,
+    #[serde(default)]
+    row: Option<RowDef>,
+    #[serde(default = "default_frame_ms")]
+    frame_ms: u64,
+    // per-frame durations in milliseconds, replaces `frame_ms`
+    #[serde(default)]
+    durations: Vec<u64>,
+    // defaults to false for attack, hurt and die and true for everything else
+    #[serde(default)]
+    looping: Option<bool>,
+}
+
+// A sprite whose clips come from an Aseprite or TexturePacker JSON export
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SheetImportDef {
+    sheet: String,
+    // for frames that don't have their own duration (TexturePacker)
+    #[serde(default = "default_frame_ms")]
+    frame_ms: u64,
+    // clip name -> tag or frame name in the sheet, for sheets whose names aren't clip names
+    #[serde(default)]
+    clips: HashMap<String, String>,
+}
+
+#[derive(Clone)]
+pub struct Frame {
+    pub region: Rectangle,
+    pub duration: Duration,
+    // where to draw the region relative to the sprite's position, for trimmed sheets
+    pub offset: Vec2<f32>,
+}
+
+pub struct Clip {
+    pub texture: String,
+    pub frames: Vec<Frame>,
+    pub looping: bool,
+}
+
+impl Clip {
+    pub fn length(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    // The frame to show `time` after the clip started. Looping clips wrap around, one-shot
+    // clips stay on their last frame.
+    pub fn frame_at(&self, time: Duration) -> &Frame {
+        let length = self.length().as_nanos().max(1);
+        let mut time = time.as_nanos();
+
+        if self.looping {
+            time %= length;
+        }
+
+        for frame in &self.frames {
+            if time < frame.duration.as_nanos() {
+                return frame;
+            }
+
+            time -= frame.duration.as_nanos();
+        }
+
+        &self.frames[self.frames.len() - 1]
+    }
+
+    pub fn finished(&self, time: Duration) -> bool {
+        !self.looping && time >= self.length()
+    }
+}
+
+// Every clip of one sprite. Clips are named `<state>` or `<state>_<direction>`, e.g.
+// `idle` or `walk_up_left`.
+pub struct AnimationSet {
+    clips: HashMap<(AnimationState, Option<Direction>), Clip>,
+    slices: HashMap<String, Slice>,
+}
+
+impl AnimationSet {
+    // The clip for `state` facing `direction`. Diagonals use their own clip where there is
+    // one and the sideways clip otherwise, and every direction falls back to the clip
+    // without one.
+    pub fn find(&self, state: AnimationState, direction: Direction) -> Option<&Clip> {
+        let exact = self.clips.get(&(state, Some(direction)));
+        let sideways = if direction.is_diagonal() {
+            self.clips.get(&(state, Some(direction.horizontal())))
+        } else {
+            None
+        };
+
+        exact.or(sideways).or_else(|| self.clips.get(&(state, None)))
+    }
+
+    // what to draw, sprites without a clip for a state show their idle clip instead
+    pub fn clip_for(&self, state: AnimationState, direction: Direction) -> Option<&Clip> {
+        self.find(state, direction).or_else(|| self.find(AnimationState::Idle, direction))
+    }
+
+    pub fn has(&self, state: AnimationState) -> bool {
+        self.clips.keys().any(|(clip_state, _)| *clip_state == state)
+    }
+
+    pub fn clips(&self) -> impl Iterator<Item = &Clip> {
+        self.clips.values()
+    }
+
+    // a slice drawn in Aseprite, e.g. `hitbox`
+    pub fn slice(&self, name: &str) -> Option<&Slice> {
+        self.slices.get(name)
+    }
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Json(String),
+    UnknownClip { sprite: String, clip: String },
+    NoFrames { sprite: String, clip: String },
+    WrongDurationCount { sprite: String, clip: String, frames: usize, durations: usize },
+    Sheet { sprite: String, path: String, message: String },
+    MissingAnimation { sprite: String, path: String, name: String },
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationError::Json(message) => write!(f, "invalid animations: {}", message),
+            AnimationError::UnknownClip { sprite, clip } => write!(
+                f,
+                "`{}`: `{}` is not a state (idle, walk, attack, hurt, die) with an optional direction",
+                sprite, clip
+            ),
+            AnimationError::NoFrames { sprite, clip } => write!(f, "`{}`: clip `{}` has no frames", sprite, clip),
+            AnimationError::WrongDurationCount { sprite, clip, frames, durations } => write!(
+                f,
+                "`{}`: clip `{}` has {} frames but {} durations",
+                sprite, clip, frames, durations
+            ),
+            AnimationError::Sheet { sprite, path, message } => write!(f, "`{}`: {}: {}", sprite, path, message),
+            AnimationError::MissingAnimation { sprite, path, name } => {
+                write!(f, "`{}`: {} has no tag or frames named `{}`", sprite, path, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+// splits `walk_up_left` into (Walk, Some(UpLeft))
+fn parse_clip_name(name: &str) -> Option<(AnimationState, Option<Direction>)> {
+    for state in AnimationState::ALL.iter().copied() {
+        if name == state.name() {
+            return Some((state, None));
+        }
+
+        if let Some(direction) = name.strip_prefix(state.name()).and_then(|rest| rest.strip_prefix('_')) {
+            return Direction::from_name(direction).map(|direction| (state, Some(direction)));
+        }
+    }
+
+    None
+}
+
+fn parse_clips(sprite: &str, defs: HashMap<String, ClipDef>, directory: &Path) -> Result<AnimationSet, AnimationError> {
+    let mut clips = HashMap::new();
+
+    for (name, def) in defs {
+        let key = parse_clip_name(&name).ok_or_else(|| AnimationError::UnknownClip {
+            sprite: sprite.to_string(),
+            clip: name.clone(),
+        })?;
+
+        let mut regions: Vec<Rectangle> = def
+            .frames
+            .iter()
+            .map(|&[x, y, width, height]| Rectangle::new(x, y, width, height))
+            .collect();
+
+        if let Some(row) = &def.row {
+            regions.extend(Rectangle::row(row.x, row.y, row.width, row.height).take(row.count));
+        }
+
+        if regions.is_empty() {
+            return Err(AnimationError::NoFrames { sprite: sprite.to_string(), clip: name });
+        }
+
+        let durations: Vec<Duration> = if def.durations.is_empty() {
+            vec![Duration::from_millis(def.frame_ms); regions.len()]
+        } else if def.durations.len() == regions.len() {
+            def.durations.iter().map(|&ms| Duration::from_millis(ms)).collect()
+        } else {
+            return Err(AnimationError::WrongDurationCount {
+                sprite: sprite.to_string(),
+                clip: name,
+                frames: regions.len(),
+                durations: def.durations.len(),
+            });
+        };
+
+        clips.insert(key, Clip {
+            texture: asset_path(directory, &def.texture),
+            frames: regions
+                .into_iter()
+                .zip(durations)
+                .map(|(region, duration)| Frame { region, duration, offset: Vec2::zero() })
+                .collect(),
+            looping: def.looping.unwrap_or(!key.0.is_one_shot()),
+        });
+    }
+
+    Ok(AnimationSet { clips, slices: HashMap::new() })
+}
+
+// Turns the tags (or frame groups) of an exported sprite sheet into clips. Without a
+// `clips` map every tag must be named like a clip, with one only the listed tags are used.
+fn import_sheet(sprite: &str, def: SheetImportDef, directory: &Path) -> Result<AnimationSet, AnimationError> {
+    let path = asset_path(directory, &def.sheet);
+    let sheet_error = |message: String| AnimationError::Sheet {
+        sprite: sprite.to_string(),
+        path: path.clone(),
+        message,
+    };
+
+    let source = fs::read_to_string(&path).map_err(|error| sheet_error(error.to_string()))?;
+    let sheet = SpriteSheet::parse(&source, Path::new(&path), def.frame_ms)
+        .map_err(|error| sheet_error(error.to_string()))?;
+
+    let names: Vec<(String, String)> = if def.clips.is_empty() {
+        sheet.animations.iter().map(|animation| (animation.name.clone(), animation.name.clone())).collect()
+    } else {
+        def.clips.iter().map(|(clip, name)| (clip.clone(), name.clone())).collect()
+    };
+
+    let mut clips = HashMap::new();
+
+    for (clip, name) in names {
+        let key = parse_clip_name(&clip).ok_or_else(|| AnimationError::UnknownClip {
+            sprite: sprite.to_string(),
+            clip: clip.clone(),
+        })?;
+        let animation = sheet
+            .animations
+            .iter()
+            .find(|animation| animation.name == name)
+            .ok_or_else(|| AnimationError::MissingAnimation {
+                sprite: sprite.to_string(),
+                path: path.clone(),
+                name,
+            })?;
+
+        clips.insert(key, Clip {
+            texture: sheet.image.clone(),
+            frames: animation.frames.clone(),
+            looping: animation.looping.unwrap_or(!key.0.is_one_shot()),
+        });
+    }
+
+    let slices = sheet.slices.into_iter().map(|slice| (slice.name.clone(), slice)).collect();
+
+    Ok(AnimationSet { clips, slices })
+}
+
+// Animation clips for every sprite, keyed by the sprite names prefabs use.
+pub struct Animations {
+    sets: HashMap<String, AnimationSet>,
+}
+
+impl Animations {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Animations> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        load_asset(path, |source| Animations::parse_in(source, directory))
+    }
+
+    pub fn parse(source: &str) -> Result<Animations, AnimationError> {
+        Animations::parse_in(source, Path::new(""))
+    }
+
+    // Like `parse`, with textures and sprite sheets relative to `directory`.
+    fn parse_in(source: &str, directory: &Path) -> Result<Animations, AnimationError> {
+        let file: HashMap<String, serde_json::Value> =
+            serde_json::from_str(source).map_err(|error| AnimationError::Json(error.to_string()))?;
+        let mut sets: HashMap<String, AnimationSet> = HashMap::new();
+
+        for (sprite, value) in file {
+            // a sprite is either a map of clips or a sprite sheet to import
+            let set = if value.get("sheet").is_some() {
+                let def: SheetImportDef = serde_json::from_value(value)
+                    .map_err(|error| AnimationError::Json(format!("`{}`: {}", sprite, error)))?;
+                import_sheet(&sprite, def, directory)?
+            } else {
+                let defs: HashMap<String, ClipDef> = serde_json::from_value(value)
+                    .map_err(|error| AnimationError::Json(format!("`{}`: {}", sprite, error)))?;
+                parse_clips(&sprite, defs, directory)?
+            };
+
+            sets.insert(sprite, set);
+        }
+
+        Ok(Animations { sets })
+    }
+
+    pub fn get(&self, sprite: &str) -> Option<&AnimationSet> {
+        self.sets.get(sprite)
+    }
+
+    pub fn sets(&self) -> impl Iterator<Item = &AnimationSet> {
+        self.sets.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Animations, AnimationError> {
+        Animations::parse(source)
+    }
+
+    // A sprite whose clips are told apart by the texture they are drawn from.
+    const SPRITE: &str = r#"{ "knight": {
+        "idle": { "texture": "idle.png", "row": { "width": 16, "height": 16, "count": 2 } },
+        "walk_left": { "texture": "walk_left.png", "row": { "width": 16, "height": 16, "count": 4 } },
+        "walk_right": { "texture": "walk_right.png", "row": { "width": 16, "height": 16, "count": 4 } },
+        "walk_up_right": { "texture": "walk_up_right.png", "row": { "width": 16, "height": 16, "count": 4 } },
+        "walk": { "texture": "walk.png", "row": { "width": 16, "height": 16, "count": 4 } },
+        "attack": { "texture": "attack.png", "frames": [[0, 0, 16, 16], [16, 0, 16, 16], [32, 0, 16, 16]], "durations": [50, 100, 150] },
+        "hurt_left": { "texture": "hurt.png", "frames": [[0, 0, 16, 16]] },
+        "die": { "texture": "die.png", "row": { "x": 0, "y": 16, "width": 16, "height": 16, "count": 2 }, "looping": true }
+    } }"#;
+
+    fn knight(animations: &Animations) -> &AnimationSet {
+        animations.get("knight").unwrap()
+    }
+
+    fn texture(clip: Option<&Clip>) -> Option<&str> {
+        clip.map(|clip| clip.texture.as_str())
+    }
+
+    #[test]
+    fn looping_clips_wrap_and_one_shots_hold() {
+        let animations = parse(SPRITE).unwrap();
+        let set = knight(&animations);
+
+        // two 100ms frames
+        let idle = set.find(AnimationState::Idle, Direction::Down).unwrap();
+        assert!(idle.looping);
+        assert_eq!(idle.length(), Duration::from_millis(200));
+        assert_eq!(idle.frame_at(Duration::from_millis(150)).region.x, 16.0);
+        assert_eq!(idle.frame_at(Duration::from_millis(250)).region.x, 0.0);
+        assert!(!idle.finished(Duration::from_secs(10)));
+
+        // frames of 50, 100 and 150ms
+        let attack = set.find(AnimationState::Attack, Direction::Down).unwrap();
+        assert!(!attack.looping);
+        assert_eq!(attack.frame_at(Duration::from_millis(49)).region.x, 0.0);
+        assert_eq!(attack.frame_at(Duration::from_millis(50)).region.x, 16.0);
+        assert_eq!(attack.frame_at(Duration::from_millis(299)).region.x, 32.0);
+        assert!(!attack.finished(Duration::from_millis(299)));
+        assert_eq!(attack.frame_at(Duration::from_millis(1000)).region.x, 32.0);
+        assert!(attack.finished(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn looping_defaults_to_off_for_attack_hurt_and_die() {
+        let animations = parse(SPRITE).unwrap();
+        let set = knight(&animations);
+        let looping = |state: AnimationState| set.find(state, Direction::Left).unwrap().looping;
+
+        assert!(looping(AnimationState::Idle));
+        assert!(looping(AnimationState::Walk));
+        assert!(!looping(AnimationState::Attack));
+        assert!(!looping(AnimationState::Hurt));
+
+        // unless the clip says otherwise
+        assert!(looping(AnimationState::Die));
+    }
+
+    #[test]
+    fn directions_fall_back() {
+        let animations = parse(SPRITE).unwrap();
+        let set = knight(&animations);
+        let walk = |direction: Direction| texture(set.find(AnimationState::Walk, direction));
+
+        assert_eq!(walk(Direction::Left), Some("walk_left.png"));
+        assert_eq!(walk(Direction::UpRight), Some("walk_up_right.png"));
+
+        // diagonals without their own clip use the sideways one
+        assert_eq!(walk(Direction::DownRight), Some("walk_right.png"));
+        assert_eq!(walk(Direction::UpLeft), Some("walk_left.png"));
+
+        // and everything else the clip without a direction
+        assert_eq!(walk(Direction::Up), Some("walk.png"));
+        assert_eq!(walk(Direction::Down), Some("walk.png"));
+
+        // which a state doesn't have to have
+        assert_eq!(texture(set.find(AnimationState::Hurt, Direction::DownLeft)), Some("hurt.png"));
+        assert_eq!(texture(set.find(AnimationState::Hurt, Direction::Right)), None);
+    }
+
+    #[test]
+    fn the_sorcerer_walks_diagonally_with_its_sideways_clips() {
+        let animations = Animations::load("./resources/animations.json").unwrap();
+        let sorcerer = animations.get("sorcerer").unwrap();
+        let walk = |direction: Direction| texture(sorcerer.find(AnimationState::Walk, direction));
+
+        // it has no diagonal art yet
+        assert_eq!(walk(Direction::DownRight), walk(Direction::Right));
+        assert_eq!(walk(Direction::UpRight), walk(Direction::Right));
+        assert_eq!(walk(Direction::DownLeft), walk(Direction::Left));
+        assert_eq!(walk(Direction::UpLeft), walk(Direction::Left));
+        assert_ne!(walk(Direction::Up), walk(Direction::Right));
+        assert_ne!(walk(Direction::Down), walk(Direction::Left));
+    }
+
+    #[test]
+    fn walk_falls_back_to_idle() {
+        let source = r#"{ "barrel": {
+            "idle": { "texture": "barrel.png", "frames": [[0, 0, 32, 32]] }
+        } }"#;
+        let animations = parse(source).unwrap();
+        let set = animations.get("barrel").unwrap();
+
+        assert!(set.find(AnimationState::Walk, Direction::Left).is_none());
+        assert!(!set.has(AnimationState::Walk));
+        assert_eq!(texture(set.clip_for(AnimationState::Walk, Direction::Left)), Some("barrel.png"));
+        assert_eq!(texture(set.clip_for(AnimationState::Die, Direction::Up)), Some("barrel.png"));
+
+        // states with a clip of their own use it
+        let animations = parse(SPRITE).unwrap();
+        assert_eq!(texture(knight(&animations).clip_for(AnimationState::Walk, Direction::Up)), Some("walk.png"));
+    }
+
+    #[test]
+    fn errors() {
+        let wrong_durations = r#"{ "knight": { "attack": { "texture": "attack.png", "row": { "width": 16, "height": 16, "count": 3 }, "durations": [50, 100] } } }"#;
+        assert!(matches!(
+            parse(wrong_durations),
+            Err(AnimationError::WrongDurationCount { ref clip, frames: 3, durations: 2, .. }) if clip == "attack"
+        ));
+
+        let no_frames = r#"{ "knight": { "idle": { "texture": "idle.png" } } }"#;
+        assert!(matches!(
+            parse(no_frames),
+            Err(AnimationError::NoFrames { ref sprite, ref clip }) if sprite == "knight" && clip == "idle"
+        ));
+
+        for clip in ["dance", "walk_sideways", "walkleft", "idle_"].iter() {
+            let unknown = format!(r#"{{ "knight": {{ "{}": {{ "texture": "idle.png", "frames": [[0, 0, 16, 16]] }} }} }}"#, clip);
+            assert!(
+                matches!(parse(&unknown), Err(AnimationError::UnknownClip { clip: ref name, .. }) if name == clip),
+                "{}",
+                clip
+            );
+        }
+    }
+}
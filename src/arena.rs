@@ -0,0 +1,311 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+
+// Refers to one entity in an `Arena`. A slot gets a new generation every time its entity
+// is removed, so a handle to a removed entity never points at whatever reuses the slot.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    // the slot this handle refers to, for storages that sit alongside an arena
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// written out by hand, deriving these would require T to implement them too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Handle<T>) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+    // queued in `despawn_queue`, kept here too so checking doesn't search the queue
+    despawning: bool,
+}
+
+// Storage for one kind of entity. Entities are looked up by `Handle` rather than by
+// position in a list, so removing one never changes which entity another handle refers
+// to.
+//
+// Removal during a tick goes through `despawn`, which only queues the entity. Queued
+// entities stay in the arena, so loops over it are unaffected, until `apply_despawns`
+// removes all of them at once.
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+    despawn_queue: Vec<Handle<T>>,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            despawn_queue: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        self.len += 1;
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize].value = Some(value);
+                index
+            },
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                    despawning: false,
+                });
+                (self.slots.len() - 1) as u32
+            },
+        };
+
+        Handle {
+            index,
+            generation: self.slots[index as usize].generation,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        match self.slots.get(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        match self.slots.get_mut(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    // Removes an entity straight away. Use `despawn` instead while looping over the arena.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let slot = match self.slots.get_mut(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation && slot.value.is_some() => slot,
+            _ => return None,
+        };
+
+        let value = slot.value.take();
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.despawning = false;
+        self.free.push(handle.index);
+        self.len -= 1;
+
+        value
+    }
+
+    // Queues an entity to be removed by the next `apply_despawns`. Queuing the same
+    // entity more than once is fine.
+    pub fn despawn(&mut self, handle: Handle<T>) {
+        match self.slots.get_mut(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation && slot.value.is_some() && !slot.despawning => {
+                slot.despawning = true;
+                self.despawn_queue.push(handle);
+            },
+            _ => (),
+        }
+    }
+
+    pub fn is_despawning(&self, handle: Handle<T>) -> bool {
+        match self.slots.get(handle.index as usize) {
+            Some(slot) => slot.generation == handle.generation && slot.despawning,
+            None => false,
+        }
+    }
+
+    // Removes every queued entity, in the order they were queued, and returns their
+    // handles so anything else keyed by them can be cleaned up too.
+    pub fn apply_despawns(&mut self) -> Vec<Handle<T>> {
+        let queue = mem::take(&mut self.despawn_queue);
+
+        for handle in &queue {
+            self.remove(*handle);
+        }
+
+        queue
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        let handles: Vec<Handle<T>> = self.handles().collect();
+
+        for handle in handles {
+            self.remove(handle);
+        }
+
+        self.despawn_queue.clear();
+    }
+
+    // Iteration goes through the slots in order, so it is the same every run.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| {
+                (
+                    Handle {
+                        index: index as u32,
+                        generation: slot.generation,
+                        marker: PhantomData,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T>, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let generation = slot.generation;
+
+            slot.value.as_mut().map(|value| {
+                (
+                    Handle {
+                        index: index as u32,
+                        generation,
+                        marker: PhantomData,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = Handle<T>> + '_ {
+        self.iter().map(|(handle, _)| handle)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Arena<T> {
+        Arena::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_values_can_be_found_by_handle() {
+        let mut arena = Arena::new();
+        let first = arena.insert("first");
+        let second = arena.insert("second");
+
+        assert_eq!(arena.get(first), Some(&"first"));
+        assert_eq!(arena.get(second), Some(&"second"));
+        assert_eq!(arena.len(), 2);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn removed_values_are_gone() {
+        let mut arena = Arena::new();
+        let first = arena.insert(1);
+        let second = arena.insert(2);
+
+        assert_eq!(arena.remove(first), Some(1));
+        assert_eq!(arena.remove(first), None);
+        assert!(!arena.contains(first));
+        assert_eq!(arena.get(second), Some(&2));
+        assert_eq!(arena.len(), 1);
+        assert_eq!(arena.values().collect::<Vec<_>>(), vec![&2]);
+    }
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut arena = Arena::new();
+        let old = arena.insert(1);
+        arena.remove(old);
+        let new = arena.insert(2);
+
+        // same slot, but the old handle doesn't reach the new value
+        assert_eq!(new.index(), old.index());
+        assert_ne!(new, old);
+        assert_eq!(arena.get(old), None);
+        assert_eq!(arena.get_mut(old), None);
+        assert_eq!(arena.remove(old), None);
+        assert_eq!(arena.get(new), Some(&2));
+    }
+
+    #[test]
+    fn despawns_wait_for_apply_despawns() {
+        let mut arena = Arena::new();
+        let first = arena.insert(1);
+        let second = arena.insert(2);
+
+        arena.despawn(second);
+        arena.despawn(first);
+        arena.despawn(second);
+
+        assert!(arena.is_despawning(first));
+        assert!(arena.contains(first));
+        assert_eq!(arena.len(), 2);
+
+        assert_eq!(arena.apply_despawns(), vec![second, first]);
+        assert!(arena.is_empty());
+        assert!(!arena.is_despawning(first));
+
+        // a new entity in a despawned slot isn't despawning
+        let third = arena.insert(3);
+        assert!(!arena.is_despawning(third));
+        assert!(arena.apply_despawns().is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use tetra::audio::Sound;
+use tetra::graphics::{Font, Texture};
+use tetra::Context;
+
+// not a path, so it can never clash with a texture on disk
+const WHITE_PIXEL: &str = "<white pixel>";
+
+// Every asset is loaded from disk the first time its path is requested and shared
+// afterwards. Texture, Font and Sound are reference counted by Tetra, so handing out
+// clones doesn't copy any data.
+pub struct Assets {
+    textures: HashMap<String, Texture>,
+    fonts: HashMap<String, Font>,
+    sounds: HashMap<String, Sound>,
+}
+
+impl Assets {
+    pub fn new() -> Assets {
+        Assets {
+            textures: HashMap::new(),
+            fonts: HashMap::new(),
+            sounds: HashMap::new(),
+        }
+    }
+
+    pub fn texture(&mut self, ctx: &mut Context, path: &str) -> tetra::Result<Texture> {
+        if let Some(texture) = self.textures.get(path) {
+            return Ok(texture.clone());
+        }
+
+        let texture = Texture::new(ctx, path)?;
+        self.textures.insert(path.to_string(), texture.clone());
+
+        Ok(texture)
+    }
+
+    // a 1x1 white texture, scaled and tinted to draw plain rectangles
+    pub fn white_pixel(&mut self, ctx: &mut Context) -> tetra::Result<Texture> {
+        if let Some(texture) = self.textures.get(WHITE_PIXEL) {
+            return Ok(texture.clone());
+        }
+
+        let texture = Texture::from_rgba(ctx, 1, 1, &[255, 255, 255, 255])?;
+        self.textures.insert(WHITE_PIXEL.to_string(), texture.clone());
+
+        Ok(texture)
+    }
+
+    pub fn font(&mut self, ctx: &mut Context, path: &str) -> tetra::Result<Font> {
+        if let Some(font) = self.fonts.get(path) {
+            return Ok(*font);
+        }
+
+        let font = Font::new(ctx, path)?;
+        self.fonts.insert(path.to_string(), font);
+
+        Ok(font)
+    }
+
+    #[allow(dead_code)] // there are no sounds in the game yet
+    pub fn sound(&mut self, path: &str) -> tetra::Result<Sound> {
+        if let Some(sound) = self.sounds.get(path) {
+            return Ok(sound.clone());
+        }
+
+        let sound = Sound::new(path)?;
+        self.sounds.insert(path.to_string(), sound.clone());
+
+        Ok(sound)
+    }
+
+    // Loads everything up front so a missing file stops the game at startup, with the
+    // failing path in the error, instead of halfway through a level.
+    pub fn preload_textures(&mut self, ctx: &mut Context, paths: &[&str]) -> tetra::Result {
+        for path in paths {
+            self.texture(ctx, path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use image::{GenericImage, RgbaImage};
+use tetra::graphics::{self, DrawParams, Rectangle, Texture};
+use tetra::{Context, TetraError};
+
+use hypoterra::game_structs::Tile;
+use hypoterra::geometry;
+use hypoterra::tilemap::TileChunks;
+use hypoterra::tiles::TileRegistry;
+
+const ATLAS_WIDTH: u32 = 1024;
+
+// Every tile texture packed into a single texture, so the whole map is drawn from one
+// texture and Tetra can batch it.
+pub struct TileAtlas {
+    texture: Texture,
+    regions: HashMap<String, Rectangle>,
+}
+
+impl TileAtlas {
+    pub fn build(ctx: &mut Context, registry: &TileRegistry) -> tetra::Result<TileAtlas> {
+        let mut images: Vec<(String, RgbaImage)> = Vec::new();
+
+        for def in registry.defs() {
+            if images.iter().any(|(path, _)| path == &def.texture) {
+                continue;
+            }
+
+            let image = image::open(&def.texture).map_err(|error| TetraError::FailedToLoadAsset {
+                reason: io::Error::other(error.to_string()),
+                path: PathBuf::from(&def.texture),
+            })?;
+
+            images.push((def.texture.clone(), image.to_rgba()));
+        }
+
+        // simple shelf packing, tallest images first
+        images.sort_by(|a, b| b.1.height().cmp(&a.1.height()).then(a.0.cmp(&b.0)));
+
+        let mut placements: Vec<(u32, u32)> = Vec::new();
+        let mut atlas_width = 1;
+        let mut cursor_x = 0;
+        let mut cursor_y = 0;
+        let mut shelf_height = 0;
+
+        for (_, image) in &images {
+            if cursor_x + image.width() > ATLAS_WIDTH && cursor_x > 0 {
+                cursor_x = 0;
+                cursor_y += shelf_height;
+                shelf_height = 0;
+            }
+
+            placements.push((cursor_x, cursor_y));
+            cursor_x += image.width();
+            shelf_height = shelf_height.max(image.height());
+            atlas_width = atlas_width.max(cursor_x);
+        }
+
+        let atlas_height = (cursor_y + shelf_height).max(1);
+
+        let mut atlas = RgbaImage::new(atlas_width, atlas_height);
+        let mut regions: HashMap<String, Rectangle> = HashMap::new();
+
+        for ((path, image), (x, y)) in images.iter().zip(placements) {
+            atlas.copy_from(image, x, y).map_err(|error| TetraError::FailedToLoadAsset {
+                reason: io::Error::other(error.to_string()),
+                path: PathBuf::from(path),
+            })?;
+
+            regions.insert(
+                path.clone(),
+                Rectangle::new(x as f32, y as f32, image.width() as f32, image.height() as f32),
+            );
+        }
+
+        let texture = Texture::from_rgba(ctx, atlas_width as i32, atlas_height as i32, &atlas.into_raw())?;
+
+        Ok(TileAtlas { texture, regions })
+    }
+}
+
+pub struct TileMapRenderer {
+    atlas: TileAtlas,
+    chunks: TileChunks,
+}
+
+impl TileMapRenderer {
+    pub fn new(atlas: TileAtlas, chunks: TileChunks) -> TileMapRenderer {
+        TileMapRenderer { atlas, chunks }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, tiles: &[Tile], registry: &TileRegistry, view: geometry::Rectangle, elapsed: Duration) {
+        for chunk in self.chunks.visible(view) {
+            for &index in chunk {
+                let tile = &tiles[index];
+                let def = match registry.get(tile.id) {
+                    Some(def) => def,
+                    None => continue,
+                };
+                let region = self.atlas.regions[&def.texture];
+
+                // clips from the registry are relative to the tile's own texture
+                let clip = match def.clip(elapsed) {
+                    Some(clip) => Rectangle::new(region.x + clip.x, region.y + clip.y, clip.width, clip.height),
+                    None => region,
+                };
+
+                graphics::draw(ctx, &self.atlas.texture, DrawParams::new().position(tile.position).clip(clip));
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::geometry::Rectangle;
+use crate::util::load_json;
+
+// How the camera follows the player. Distances are in world pixels, times in seconds.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    // How quickly the camera catches up with the player, roughly the fraction of the
+    // remaining distance covered per 1/smoothing seconds. 0 snaps to the player.
+    pub smoothing: f32,
+    // the player can move this far around the middle of the screen before the camera follows
+    pub dead_zone: [f32; 2],
+    // 2.0 shows everything twice as big
+    pub zoom: f32,
+    // keep the view inside the level, smaller levels are centred
+    pub clamp_to_level: bool,
+    // the furthest the view moves at full trauma
+    pub shake_offset: [f32; 2],
+    // the furthest the view turns at full trauma, in radians
+    pub shake_angle: f32,
+    // how fast the shake wobbles
+    pub shake_frequency: f32,
+    // trauma lost per second
+    pub trauma_decay: f32,
+    // trauma added when the player is hurt and when something dies
+    pub hit_trauma: f32,
+    pub kill_trauma: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> CameraConfig {
+        CameraConfig {
+            smoothing: 0.0,
+            dead_zone: [0.0, 0.0],
+            zoom: 1.0,
+            clamp_to_level: true,
+            shake_offset: [24.0, 24.0],
+            shake_angle: 0.05,
+            shake_frequency: 15.0,
+            trauma_decay: 1.5,
+            hit_trauma: 0.5,
+            kill_trauma: 0.3,
+        }
+    }
+}
+
+impl CameraConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<CameraConfig> {
+        load_json(path)
+    }
+}
+
+// Everything the camera remembers between ticks. `focus` is the middle of the view
+// before shaking, `trauma` goes from 0 (still) to 1 (shaking as hard as it can).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CameraState {
+    pub focus: Vec2<f32>,
+    pub trauma: f32,
+    // seconds since the camera was created, drives the shake
+    pub time: f32,
+}
+
+impl CameraState {
+    pub fn new(focus: Vec2<f32>) -> CameraState {
+        CameraState {
+            focus,
+            trauma: 0.0,
+            time: 0.0,
+        }
+    }
+}
+
+// Where the camera ends up this tick: the middle of the view, its rotation and zoom.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CameraView {
+    pub position: Vec2<f32>,
+    pub rotation: f32,
+    pub zoom: f32,
+}
+
+// How much of the world a `viewport` sized window shows at `zoom`.
+pub fn view_size(viewport: Vec2<f32>, zoom: f32) -> Vec2<f32> {
+    viewport / zoom.max(0.01)
+}
+
+// Moves `center` so a view of `size` stays inside `level`. On an axis where the level is
+// smaller than the view the view is centred on the level instead.
+pub fn clamp_to_level(center: Vec2<f32>, size: Vec2<f32>, level: Rectangle) -> Vec2<f32> {
+    let clamp_axis = |center: f32, size: f32, start: f32, length: f32| {
+        if size >= length {
+            start + length / 2.0
+        } else {
+            center.max(start + size / 2.0).min(start + length - size / 2.0)
+        }
+    };
+
+    Vec2::new(
+        clamp_axis(center.x, size.x, level.x, level.width),
+        clamp_axis(center.y, size.y, level.y, level.height),
+    )
+}
+
+// Where the focus wants to be: unchanged while `target` is inside the dead zone, else
+// just far enough to put `target` back on its edge.
+pub fn dead_zone_goal(focus: Vec2<f32>, target: Vec2<f32>, dead_zone: Vec2<f32>) -> Vec2<f32> {
+    let axis = |focus: f32, target: f32, half: f32| {
+        if target > focus + half {
+            target - half
+        } else if target < focus - half {
+            target + half
+        } else {
+            focus
+        }
+    };
+
+    Vec2::new(
+        axis(focus.x, target.x, dead_zone.x / 2.0),
+        axis(focus.y, target.y, dead_zone.y / 2.0),
+    )
+}
+
+// Smooth noise between -1 and 1, a few sine waves that never line up. `seed` picks a
+// different wave for each axis.
+fn wobble(time: f32, seed: f32) -> f32 {
+    ((time + seed).sin() + (time * 2.3 + seed * 1.7).sin() * 0.5 + (time * 4.1 + seed * 3.1).sin() * 0.25) / 1.75
+}
+
+// Advances the camera by `dt` seconds towards `target`, usually the middle of the player.
+// Pure, so the whole camera behaviour can be checked without a window.
+pub fn step_camera(
+    state: CameraState,
+    target: Vec2<f32>,
+    level: Rectangle,
+    viewport: Vec2<f32>,
+    config: &CameraConfig,
+    dt: f32,
+) -> CameraState {
+    let goal = dead_zone_goal(state.focus, target, Vec2::from(config.dead_zone));
+
+    // exponential smoothing, so it behaves the same whatever `dt` is
+    let amount = if config.smoothing <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-config.smoothing * dt).exp()
+    };
+    let mut focus = state.focus + (goal - state.focus) * amount;
+
+    if config.clamp_to_level {
+        focus = clamp_to_level(focus, view_size(viewport, config.zoom), level);
+    }
+
+    CameraState {
+        focus,
+        trauma: (state.trauma - config.trauma_decay * dt).max(0.0),
+        time: state.time + dt,
+    }
+}
+
+// What to show for `state`, with the shake added on top of the focus. Shake grows with the
+// square of trauma so small knocks stay subtle.
+pub fn view_for(state: CameraState, level: Rectangle, viewport: Vec2<f32>, config: &CameraConfig) -> CameraView {
+    let shake = state.trauma.min(1.0).powi(2);
+    let time = state.time * config.shake_frequency;
+    let offset = Vec2::new(
+        config.shake_offset[0] * shake * wobble(time, 0.0),
+        config.shake_offset[1] * shake * wobble(time, 10.0),
+    );
+    let mut position = state.focus + offset;
+
+    if config.clamp_to_level {
+        position = clamp_to_level(position, view_size(viewport, config.zoom), level);
+    }
+
+    CameraView {
+        position,
+        rotation: config.shake_angle * shake * wobble(time, 20.0),
+        zoom: config.zoom,
+    }
+}
+
+// What the world is seen through: the middle of the view, its rotation and zoom, and the
+// size of the window it is shown in. The front end turns it into a transform when drawing.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Camera {
+    pub position: Vec2<f32>,
+    pub rotation: f32,
+    pub zoom: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl Camera {
+    pub fn new(viewport_width: f32, viewport_height: f32) -> Camera {
+        Camera {
+            position: Vec2::zero(),
+            rotation: 0.0,
+            zoom: 1.0,
+            viewport_width,
+            viewport_height,
+        }
+    }
+}
+
+// Copies a view onto the camera.
+pub fn apply_view(camera: &mut Camera, view: CameraView) {
+    camera.position = view.position;
+    camera.rotation = view.rotation;
+    camera.zoom = view.zoom;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestep::DT;
+
+    const LEVEL: Rectangle = Rectangle::new(0.0, 0.0, 2560.0, 2560.0);
+
+    fn viewport() -> Vec2<f32> {
+        Vec2::new(1280.0, 960.0)
+    }
+
+    #[test]
+    fn stays_put_while_the_target_is_in_the_dead_zone() {
+        let config = CameraConfig {
+            dead_zone: [96.0, 64.0],
+            ..CameraConfig::default()
+        };
+        let state = CameraState::new(Vec2::new(1000.0, 1000.0));
+
+        for target in &[Vec2::new(1040.0, 1000.0), Vec2::new(952.0, 1031.0), Vec2::new(1048.0, 968.0)] {
+            assert_eq!(step_camera(state, *target, LEVEL, viewport(), &config, DT).focus, state.focus);
+        }
+
+        // just past the edge only moves far enough to put the target back on it
+        let moved = step_camera(state, Vec2::new(1058.0, 1000.0), LEVEL, viewport(), &config, DT);
+        assert_eq!(moved.focus, Vec2::new(1010.0, 1000.0));
+    }
+
+    #[test]
+    fn is_clamped_to_the_edges_of_the_map() {
+        let config = CameraConfig::default();
+
+        let top_left = step_camera(CameraState::new(Vec2::zero()), Vec2::new(10.0, 10.0), LEVEL, viewport(), &config, DT);
+        assert_eq!(top_left.focus, Vec2::new(640.0, 480.0));
+
+        let bottom_right = step_camera(CameraState::new(Vec2::zero()), Vec2::new(2550.0, 2550.0), LEVEL, viewport(), &config, DT);
+        assert_eq!(bottom_right.focus, Vec2::new(1920.0, 2080.0));
+
+        // a level narrower than the view is centred instead
+        let small = Rectangle::new(0.0, 0.0, 640.0, 2560.0);
+        let centred = step_camera(CameraState::new(Vec2::zero()), Vec2::new(10.0, 1000.0), small, viewport(), &config, DT);
+        assert_eq!(centred.focus, Vec2::new(320.0, 1000.0));
+
+        // zooming in shows less, so the camera gets closer to the edge
+        let zoomed = CameraConfig { zoom: 2.0, ..CameraConfig::default() };
+        let zoomed = step_camera(CameraState::new(Vec2::zero()), Vec2::new(10.0, 10.0), LEVEL, viewport(), &zoomed, DT);
+        assert_eq!(zoomed.focus, Vec2::new(320.0, 240.0));
+    }
+
+    #[test]
+    fn smoothing_converges_on_the_target() {
+        let config = CameraConfig {
+            smoothing: 8.0,
+            ..CameraConfig::default()
+        };
+        let target = Vec2::new(1500.0, 1200.0);
+        let mut state = CameraState::new(Vec2::new(1000.0, 1000.0));
+
+        let first = step_camera(state, target, LEVEL, viewport(), &config, DT);
+        assert!(first.focus.x > state.focus.x && first.focus.x < target.x);
+        assert!(first.focus.y > state.focus.y && first.focus.y < target.y);
+
+        let mut distance = state.focus.distance(target);
+        for _ in 0..120 {
+            state = step_camera(state, target, LEVEL, viewport(), &config, DT);
+
+            let next = state.focus.distance(target);
+            assert!(next <= distance);
+            distance = next;
+        }
+
+        assert!(distance < 0.01);
+    }
+
+    #[test]
+    fn trauma_decays_to_zero() {
+        let config = CameraConfig {
+            trauma_decay: 1.5,
+            ..CameraConfig::default()
+        };
+        let mut state = CameraState {
+            trauma: 1.0,
+            ..CameraState::new(Vec2::new(1000.0, 1000.0))
+        };
+
+        let shaken = view_for(state, LEVEL, viewport(), &config);
+        assert_ne!(shaken.position, state.focus);
+
+        // 1/1.5 seconds, and a few more ticks to be sure it stops at 0
+        for _ in 0..45 {
+            state = step_camera(state, state.focus, LEVEL, viewport(), &config, DT);
+        }
+
+        assert_eq!(state.trauma, 0.0);
+
+        let still = view_for(state, LEVEL, viewport(), &config);
+        assert_eq!(still.position, state.focus);
+        assert_eq!(still.rotation, 0.0);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::ai::{AiParams, AiState};
+use crate::animation::AnimationState;
+use crate::direction::Direction;
+
+// Where an entity is. `previous_position` is where it was at the start of the tick, so the
+// renderer can blend between the two.
+pub struct Transform {
+    pub position: Vec2<f32>,
+    pub previous_position: Vec2<f32>,
+}
+
+impl Transform {
+    pub fn new(position: Vec2<f32>) -> Transform {
+        Transform {
+            position,
+            previous_position: position,
+        }
+    }
+}
+
+// pixels per second
+pub struct Velocity(pub Vec2<f32>);
+
+// What happens when a moving entity runs into a collidable tile.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WallResponse {
+    // stop against the wall and keep moving along it
+    Slide,
+    // step back out of the wall, the entity's AI turns it around
+    Turn,
+    // the entity is destroyed
+    Despawn,
+}
+
+pub struct Collider {
+    pub size: Vec2<f32>,
+    pub walls: WallResponse,
+    // set by the movement system when the last move hit a wall on that axis
+    pub blocked_x: bool,
+    pub blocked_y: bool,
+}
+
+// Which sprite the front-end draws for an entity and which of its clips is playing. The
+// animation system picks `state` and `direction` from the rest of the entity's state.
+pub struct Sprite {
+    pub name: String,
+    // sprites with a higher depth are drawn on top
+    pub depth: i32,
+    pub visible: bool,
+    pub state: AnimationState,
+    pub direction: Direction,
+    // how long `state` has been playing
+    pub animation_time: Duration,
+    // a one-shot state (attack, hurt) to start on the next animation update
+    pub trigger: Option<AnimationState>,
+}
+
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+// How an entity reacts to being hurt. For `invulnerable_for` after a hit it can't be hurt
+// again, and for `knockback_for` it is pushed away from whatever hit it at `knockback`
+// pixels per second instead of moving itself. `invulnerable` and `knocked_back` count
+// down what is left of both.
+pub struct HurtResponse {
+    pub invulnerable_for: Duration,
+    pub knockback: f32,
+    pub knockback_for: Duration,
+    pub invulnerable: Duration,
+    pub knocked_back: Duration,
+}
+
+// Comes back to life at `point` `delay` after dying instead of being despawned, `lives`
+// more times or forever if None. `dead_for` is None while alive or once it has run out of
+// lives. Checkpoints move `point`.
+pub struct Respawn {
+    pub point: Vec2<f32>,
+    pub delay: Duration,
+    pub lives: Option<u32>,
+    pub dead_for: Option<Duration>,
+}
+
+// Touching it moves the respawn point of whatever can respawn.
+pub struct Checkpoint {
+    pub reached: bool,
+}
+
+// Entities only hurt entities on other teams.
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
+// An enemy's brain, see `ai::think`. `post` is where it spawned and returns to.
+pub struct Ai {
+    pub state: AiState,
+    pub time_in_state: Duration,
+    pub post: Vec2<f32>,
+    pub facing: Direction,
+    pub params: AiParams,
+}
+
+// Something the player can talk to by standing next to it. `dialogue` names one of the
+// world's `Dialogues`.
+pub struct Interaction {
+    pub prompt: String,
+    pub dialogue: String,
+}
+
+// what contact damage and projectiles without a `kind` deal
+pub const PHYSICAL: &str = "physical";
+
+// Damages the first entity of another team it touches and is destroyed with the hit.
+// `kind` is matched against the target's resistances, e.g. `arcane`.
+pub struct Projectile {
+    pub damage: i32,
+    pub kind: String,
+}
+
+// Damages entities of other teams every tick they overlap.
+pub struct ContactDamage {
+    pub damage: i32,
+    pub kind: String,
+}
+
+// Takes `armour` off every hit, then the resistance to the kind of damage as a fraction:
+// 0.5 halves it, 1 ignores it and -0.5 takes half as much again.
+pub struct Defense {
+    pub armour: i32,
+    pub resistances: HashMap<String, f32>,
+}
+
+impl Defense {
+    // what's left of a hit of `amount` after armour and resistance, never below 0
+    pub fn reduce(&self, amount: i32, kind: &str) -> i32 {
+        let resistance = self.resistances.get(kind).copied().unwrap_or(0.0);
+        let after_armour = (amount - self.armour).max(0) as f32;
+
+        (after_armour * (1.0 - resistance)).round().max(0.0) as i32
+    }
+}
+
+// Moved by the player's input.
+pub struct PlayerControl {
+    // the direction the player is moving in, None while standing still
+    pub facing: Option<Direction>,
+    // the last direction the player moved in
+    pub prev_facing: Direction,
+    // the prefab fired on attack, at `attack_speed` pixels per second
+    pub attack: String,
+    pub attack_speed: f32,
+    // how many of the player's attacks can exist at once
+    pub max_attacks: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armour_comes_off_before_resistance() {
+        let defense = Defense {
+            armour: 1,
+            resistances: vec![(String::from(PHYSICAL), 0.5)].into_iter().collect(),
+        };
+
+        // (5 - 1) * 0.5
+        assert_eq!(defense.reduce(5, PHYSICAL), 2);
+        // only armour against anything else
+        assert_eq!(defense.reduce(5, "arcane"), 4);
+        // soaked up completely, and never below 0
+        assert_eq!(defense.reduce(1, PHYSICAL), 0);
+        assert_eq!(defense.reduce(0, "arcane"), 0);
+    }
+}
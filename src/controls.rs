@@ -0,0 +1,15 @@
+use vek::Vec2;
+
+// One update's worth of player input. The game fills it in from the keyboard and
+// gamepad, tests can build it by hand to drive the simulation without a window.
+#[derive(Clone, Copy, Default)]
+pub struct Input {
+    // the direction to move in, with a length between 0 and 1
+    pub movement: Vec2<f32>,
+    // pressed this update, not held
+    pub attack: bool,
+    pub interact: bool,
+    // moving through dialogue choices, pressed this update
+    pub menu_up: bool,
+    pub menu_down: bool,
+}
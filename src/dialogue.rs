@@ -0,0 +1,452 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::util::load_asset;
+
+// Conversations with NPCs, one JSON file per NPC in `resources/dialogue`. A dialogue is a
+// graph of nodes. Each node shows one or more pages of text, then either offers the player
+// some choices, moves on to its `next` node or ends the conversation. Choices and starting
+// points can depend on the story so far, and entering a node can change it.
+
+// Something about the story that has to be true.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Flag(String),
+    NotFlag(String),
+    Item(String),
+    NotItem(String),
+    // started and not finished
+    Quest(String),
+    QuestFinished(String),
+}
+
+// What entering a node does to the story.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    SetFlag(String),
+    ClearFlag(String),
+    GiveItem(String),
+    TakeItem(String),
+    StartQuest(String),
+    FinishQuest(String),
+}
+
+#[derive(Deserialize)]
+pub struct Choice {
+    pub text: String,
+    pub next: String,
+    // only offered when all of these hold
+    #[serde(default, rename = "if")]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize)]
+pub struct Node {
+    pub pages: Vec<String>,
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    // where to go after the last page when there are no choices, None ends the conversation
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+}
+
+// Where a conversation may begin, the first one whose conditions hold is used.
+#[derive(Deserialize)]
+pub struct Start {
+    pub node: String,
+    #[serde(default, rename = "if")]
+    pub conditions: Vec<Condition>,
+}
+
+#[derive(Deserialize)]
+pub struct Dialogue {
+    pub start: Vec<Start>,
+    pub nodes: HashMap<String, Node>,
+}
+
+#[derive(Debug)]
+pub enum DialogueError {
+    Json(String),
+    UnknownNode { name: String, used_by: String },
+    NoPages(String),
+}
+
+impl fmt::Display for DialogueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialogueError::Json(message) => write!(f, "invalid dialogue: {}", message),
+            DialogueError::UnknownNode { name, used_by } => {
+                write!(f, "{} goes to node `{}`, which doesn't exist", used_by, name)
+            }
+            DialogueError::NoPages(node) => write!(f, "node `{}` has no pages", node),
+        }
+    }
+}
+
+impl std::error::Error for DialogueError {}
+
+impl Dialogue {
+    // Checks every node that is gone to exists, so a typo shows up at startup instead of
+    // halfway through a conversation.
+    pub fn parse(source: &str) -> Result<Dialogue, DialogueError> {
+        let dialogue: Dialogue = serde_json::from_str(source).map_err(|error| DialogueError::Json(error.to_string()))?;
+
+        let check = |name: &str, used_by: String| {
+            if dialogue.nodes.contains_key(name) {
+                Ok(())
+            } else {
+                Err(DialogueError::UnknownNode { name: name.to_string(), used_by })
+            }
+        };
+
+        for start in &dialogue.start {
+            check(&start.node, String::from("the start"))?;
+        }
+
+        for (name, node) in &dialogue.nodes {
+            if node.pages.is_empty() {
+                return Err(DialogueError::NoPages(name.clone()));
+            }
+
+            if let Some(next) = &node.next {
+                check(next, format!("node `{}`", name))?;
+            }
+
+            for choice in &node.choices {
+                check(&choice.next, format!("a choice of node `{}`", name))?;
+            }
+        }
+
+        Ok(dialogue)
+    }
+}
+
+// Every NPC's dialogue, named after its file without the extension.
+pub struct Dialogues {
+    dialogues: HashMap<String, Dialogue>,
+}
+
+impl Dialogues {
+    // Loads every `.json` file in `directory`.
+    pub fn load<P: AsRef<Path>>(directory: P) -> crate::Result<Dialogues> {
+        let directory = directory.as_ref();
+        let failed = |reason| Error::FailedToLoadAsset { reason, path: directory.to_path_buf() };
+
+        let mut dialogues = HashMap::new();
+
+        for entry in fs::read_dir(directory).map_err(failed)? {
+            let path = entry.map_err(failed)?.path();
+
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            let dialogue = load_asset(&path, Dialogue::parse)?;
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+
+            dialogues.insert(name, dialogue);
+        }
+
+        Ok(Dialogues { dialogues })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Dialogue> {
+        self.dialogues.get(name)
+    }
+}
+
+// What the player has done so far: flags set by conversations, the items they carry and
+// the quests they've been given.
+#[derive(Default)]
+pub struct Story {
+    pub flags: HashSet<String>,
+    pub items: HashMap<String, u32>,
+    // started quests, true once finished
+    pub quests: HashMap<String, bool>,
+}
+
+impl Story {
+    pub fn new() -> Story {
+        Story::default()
+    }
+
+    pub fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Flag(flag) => self.flags.contains(flag),
+            Condition::NotFlag(flag) => !self.flags.contains(flag),
+            Condition::Item(item) => self.items.get(item).is_some_and(|count| *count > 0),
+            Condition::NotItem(item) => self.items.get(item).is_none_or(|count| *count == 0),
+            Condition::Quest(quest) => self.quests.get(quest) == Some(&false),
+            Condition::QuestFinished(quest) => self.quests.get(quest) == Some(&true),
+        }
+    }
+
+    pub fn holds_all(&self, conditions: &[Condition]) -> bool {
+        conditions.iter().all(|condition| self.holds(condition))
+    }
+
+    pub fn apply(&mut self, effect: &Effect) {
+        match effect {
+            Effect::SetFlag(flag) => {
+                self.flags.insert(flag.clone());
+            },
+            Effect::ClearFlag(flag) => {
+                self.flags.remove(flag);
+            },
+            Effect::GiveItem(item) => *self.items.entry(item.clone()).or_insert(0) += 1,
+            Effect::TakeItem(item) => {
+                if let Some(count) = self.items.get_mut(item) {
+                    *count = count.saturating_sub(1);
+                }
+            },
+            Effect::StartQuest(quest) => {
+                self.quests.entry(quest.clone()).or_insert(false);
+            },
+            Effect::FinishQuest(quest) => {
+                self.quests.insert(quest.clone(), true);
+            },
+        }
+    }
+}
+
+// Where a conversation that is going on is up to. Doesn't hold on to the dialogue itself,
+// every step is given it and the story.
+pub struct Conversation {
+    pub dialogue: String,
+    pub node: String,
+    pub page: usize,
+    // index into `choices`, only the choices whose conditions hold are counted
+    pub selected: usize,
+}
+
+impl Conversation {
+    // Starts at the first start point whose conditions hold, None if none do.
+    pub fn start(name: &str, dialogue: &Dialogue, story: &mut Story) -> Option<Conversation> {
+        let start = dialogue.start.iter().find(|start| story.holds_all(&start.conditions))?;
+        let mut conversation = Conversation {
+            dialogue: name.to_string(),
+            node: String::new(),
+            page: 0,
+            selected: 0,
+        };
+        conversation.enter(&start.node, dialogue, story);
+
+        Some(conversation)
+    }
+
+    fn enter(&mut self, node: &str, dialogue: &Dialogue, story: &mut Story) {
+        self.node = node.to_string();
+        self.page = 0;
+        self.selected = 0;
+
+        if let Some(node) = dialogue.nodes.get(node) {
+            for effect in &node.effects {
+                story.apply(effect);
+            }
+        }
+    }
+
+    pub fn text<'a>(&self, dialogue: &'a Dialogue) -> &'a str {
+        dialogue
+            .nodes
+            .get(&self.node)
+            .and_then(|node| node.pages.get(self.page))
+            .map_or("", String::as_str)
+    }
+
+    // the choices the player can make right now, only on a node's last page
+    pub fn choices<'a>(&self, dialogue: &'a Dialogue, story: &Story) -> Vec<&'a Choice> {
+        match dialogue.nodes.get(&self.node) {
+            Some(node) if self.page + 1 >= node.pages.len() => {
+                node.choices.iter().filter(|choice| story.holds_all(&choice.conditions)).collect()
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    // whether there is more to read after this page without the player choosing anything
+    pub fn has_more(&self, dialogue: &Dialogue, story: &Story) -> bool {
+        match dialogue.nodes.get(&self.node) {
+            Some(node) if self.page + 1 < node.pages.len() => true,
+            Some(node) => node.next.is_some() && self.choices(dialogue, story).is_empty(),
+            None => false,
+        }
+    }
+
+    // moves the highlighted choice up (negative) or down, wrapping around
+    pub fn select(&mut self, step: i32, dialogue: &Dialogue, story: &Story) {
+        let count = self.choices(dialogue, story).len() as i32;
+
+        if count > 0 {
+            self.selected = (self.selected as i32 + step).rem_euclid(count) as usize;
+        }
+    }
+
+    // Goes to the next page, takes the selected choice or follows the node's `next`.
+    // Returns false once the conversation is over.
+    pub fn advance(&mut self, dialogue: &Dialogue, story: &mut Story) -> bool {
+        let node = match dialogue.nodes.get(&self.node) {
+            Some(node) => node,
+            None => return false,
+        };
+
+        if self.page + 1 < node.pages.len() {
+            self.page += 1;
+            return true;
+        }
+
+        let next = match self.choices(dialogue, story).get(self.selected) {
+            Some(choice) => Some(choice.next.clone()),
+            None => node.next.clone(),
+        };
+
+        match next {
+            Some(next) => {
+                self.enter(&next, dialogue, story);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A guard who lets the player past once they've shown their pass, and remembers being
+    // rude to.
+    const GUARD: &str = r#"{
+        "start": [
+            { "node": "grumpy", "if": [{ "flag": "insulted" }] },
+            { "node": "halt" }
+        ],
+        "nodes": {
+            "halt": {
+                "pages": ["Halt!", "Who goes there?"],
+                "choices": [
+                    { "text": "Show the pass.", "next": "pass", "if": [{ "item": "pass" }] },
+                    { "text": "Make way, fool.", "next": "insult" },
+                    { "text": "Nobody.", "next": "bye" }
+                ]
+            },
+            "pass": { "pages": ["Go on then."], "effects": [{ "take_item": "pass" }, { "finish_quest": "gate" }] },
+            "insult": { "pages": ["How rude."], "effects": [{ "set_flag": "insulted" }], "next": "bye" },
+            "grumpy": { "pages": ["You again."], "effects": [{ "clear_flag": "insulted" }] },
+            "bye": { "pages": ["Off with you."] }
+        }
+    }"#;
+
+    fn guard() -> Dialogue {
+        Dialogue::parse(GUARD).unwrap()
+    }
+
+    fn choice_texts<'a>(conversation: &Conversation, dialogue: &'a Dialogue, story: &Story) -> Vec<&'a str> {
+        conversation.choices(dialogue, story).iter().map(|choice| choice.text.as_str()).collect()
+    }
+
+    #[test]
+    fn choices_are_hidden_by_their_conditions() {
+        let dialogue = guard();
+        let mut story = Story::new();
+        let mut conversation = Conversation::start("guard", &dialogue, &mut story).unwrap();
+
+        // nothing to choose until the last page
+        assert_eq!(conversation.text(&dialogue), "Halt!");
+        assert!(choice_texts(&conversation, &dialogue, &story).is_empty());
+        assert!(conversation.advance(&dialogue, &mut story));
+
+        assert_eq!(choice_texts(&conversation, &dialogue, &story), vec!["Make way, fool.", "Nobody."]);
+
+        // with the pass it is offered, first
+        story.apply(&Effect::StartQuest(String::from("gate")));
+        story.apply(&Effect::GiveItem(String::from("pass")));
+        assert_eq!(choice_texts(&conversation, &dialogue, &story), vec!["Show the pass.", "Make way, fool.", "Nobody."]);
+
+        // and taking it uses it up
+        assert!(conversation.advance(&dialogue, &mut story));
+        assert_eq!(conversation.node, "pass");
+        assert!(!story.holds(&Condition::Item(String::from("pass"))));
+        assert!(story.holds(&Condition::QuestFinished(String::from("gate"))));
+        assert!(!conversation.advance(&dialogue, &mut story));
+    }
+
+    #[test]
+    fn selection_counts_only_offered_choices() {
+        let dialogue = guard();
+        let mut story = Story::new();
+        let mut conversation = Conversation::start("guard", &dialogue, &mut story).unwrap();
+        conversation.advance(&dialogue, &mut story);
+
+        // the second offered choice is "Nobody.", the hidden one doesn't count, and going
+        // past the end wraps around
+        conversation.select(1, &dialogue, &story);
+        conversation.select(2, &dialogue, &story);
+        conversation.select(-1, &dialogue, &story);
+        assert_eq!(conversation.selected, 0);
+        conversation.select(-1, &dialogue, &story);
+        assert_eq!(conversation.selected, 1);
+
+        assert!(conversation.advance(&dialogue, &mut story));
+        assert_eq!(conversation.node, "bye");
+    }
+
+    #[test]
+    fn effects_change_later_branches() {
+        let dialogue = guard();
+        let mut story = Story::new();
+
+        let mut conversation = Conversation::start("guard", &dialogue, &mut story).unwrap();
+        assert_eq!(conversation.node, "halt");
+        conversation.advance(&dialogue, &mut story);
+
+        // "Make way, fool." sets the flag, then moves on to `bye` without a choice
+        conversation.select(0, &dialogue, &story);
+        assert!(conversation.advance(&dialogue, &mut story));
+        assert_eq!(conversation.node, "insult");
+        assert!(story.holds(&Condition::Flag(String::from("insulted"))));
+        assert!(conversation.has_more(&dialogue, &story));
+        assert!(conversation.advance(&dialogue, &mut story));
+        assert_eq!(conversation.node, "bye");
+        assert!(!conversation.has_more(&dialogue, &story));
+        assert!(!conversation.advance(&dialogue, &mut story));
+
+        // the next conversation starts somewhere else, which clears the flag again
+        let conversation = Conversation::start("guard", &dialogue, &mut story).unwrap();
+        assert_eq!(conversation.node, "grumpy");
+        assert!(!story.holds(&Condition::Flag(String::from("insulted"))));
+        assert_eq!(Conversation::start("guard", &dialogue, &mut story).unwrap().node, "halt");
+    }
+
+    #[test]
+    fn missing_nodes_are_reported_on_load() {
+        let broken = [
+            (GUARD.replace(r#""node": "halt" }"#, r#""node": "hello" }"#), "hello", "the start"),
+            (GUARD.replace(r#""next": "pass""#, r#""next": "papers""#), "papers", "a choice of node `halt`"),
+            (GUARD.replace(r#""next": "bye" },"#, r#""next": "goodbye" },"#), "goodbye", "node `insult`"),
+        ];
+
+        for (source, missing, user) in broken.iter() {
+            match Dialogue::parse(source) {
+                Err(DialogueError::UnknownNode { name, used_by }) => {
+                    assert_eq!(&name, missing);
+                    assert_eq!(&used_by, user);
+                }
+                Err(error) => panic!("wrong error: {}", error),
+                Ok(_) => panic!("`{}` was not reported", missing),
+            }
+        }
+
+        let no_pages = GUARD.replace(r#""pages": ["Off with you."]"#, r#""pages": []"#);
+        assert!(matches!(Dialogue::parse(&no_pages), Err(DialogueError::NoPages(ref node)) if node == "bye"));
+    }
+}
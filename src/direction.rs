@@ -0,0 +1,171 @@
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+use serde::Deserialize;
+use vek::Vec2;
+
+// The eight directions an entity can face, clockwise from right. Screen coordinates, so
+// `Down` is +y.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+    Up,
+    UpRight,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 8] = [
+        Direction::Right,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+        Direction::Up,
+        Direction::UpRight,
+    ];
+
+    // a vector of length 1 pointing this way
+    pub fn to_vector(self) -> Vec2<f32> {
+        match self {
+            Direction::Right => Vec2::new(1.0, 0.0),
+            Direction::DownRight => Vec2::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            Direction::Down => Vec2::new(0.0, 1.0),
+            Direction::DownLeft => Vec2::new(-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            Direction::Left => Vec2::new(-1.0, 0.0),
+            Direction::UpLeft => Vec2::new(-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+            Direction::Up => Vec2::new(0.0, -1.0),
+            Direction::UpRight => Vec2::new(FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+        }
+    }
+
+    // The direction closest to `vector`, or None for the zero vector. Each direction
+    // covers the 45 degrees around it.
+    pub fn from_vector(vector: Vec2<f32>) -> Option<Direction> {
+        if vector == Vec2::zero() {
+            return None;
+        }
+
+        let eighth = (vector.y.atan2(vector.x) / (PI / 4.0)).round() as i32;
+
+        Some(Direction::ALL[eighth.rem_euclid(8) as usize])
+    }
+
+    pub fn is_diagonal(self) -> bool {
+        matches!(self, Direction::DownRight | Direction::DownLeft | Direction::UpLeft | Direction::UpRight)
+    }
+
+    pub fn opposite(self) -> Direction {
+        let index = Direction::ALL.iter().position(|direction| *direction == self).unwrap();
+
+        Direction::ALL[(index + 4) % 8]
+    }
+
+    // The left or right half of a diagonal, for things that only have sideways versions
+    // of their diagonal frames. Straight directions are returned as they are.
+    pub fn horizontal(self) -> Direction {
+        match self {
+            Direction::DownRight | Direction::UpRight => Direction::Right,
+            Direction::DownLeft | Direction::UpLeft => Direction::Left,
+            straight => straight,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Direction> {
+        Direction::ALL.iter().copied().find(|direction| direction.name() == name)
+    }
+
+    // used in animation names, e.g. `walk_down_left`
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Right => "right",
+            Direction::DownRight => "down_right",
+            Direction::Down => "down",
+            Direction::DownLeft => "down_left",
+            Direction::Left => "left",
+            Direction::UpLeft => "up_left",
+            Direction::Up => "up",
+            Direction::UpRight => "up_right",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors_round_trip() {
+        for direction in Direction::ALL.iter().copied() {
+            let vector = direction.to_vector();
+
+            assert!((vector.magnitude() - 1.0).abs() < 1e-6, "{:?}", direction);
+            assert_eq!(Direction::from_vector(vector), Some(direction));
+            assert_eq!(Direction::from_vector(vector * 300.0), Some(direction));
+        }
+
+        assert_eq!(Direction::from_vector(Vec2::zero()), None);
+    }
+
+    #[test]
+    fn each_direction_covers_45_degrees() {
+        let at = |degrees: f32| {
+            let radians = degrees.to_radians();
+            Direction::from_vector(Vec2::new(radians.cos(), radians.sin())).unwrap()
+        };
+
+        // +y is down, so the angles go clockwise
+        assert_eq!(at(22.0), Direction::Right);
+        assert_eq!(at(23.0), Direction::DownRight);
+        assert_eq!(at(-22.0), Direction::Right);
+        assert_eq!(at(-23.0), Direction::UpRight);
+        assert_eq!(at(112.0), Direction::Down);
+        assert_eq!(at(113.0), Direction::DownLeft);
+        assert_eq!(at(179.0), Direction::Left);
+        assert_eq!(at(-179.0), Direction::Left);
+        assert_eq!(at(-113.0), Direction::UpLeft);
+
+        // a stick barely off an axis still counts as straight
+        assert_eq!(Direction::from_vector(Vec2::new(0.01, -1.0)), Some(Direction::Up));
+    }
+
+    #[test]
+    fn opposites() {
+        assert_eq!(Direction::Right.opposite(), Direction::Left);
+        assert_eq!(Direction::Up.opposite(), Direction::Down);
+        assert_eq!(Direction::DownRight.opposite(), Direction::UpLeft);
+        assert_eq!(Direction::UpRight.opposite(), Direction::DownLeft);
+
+        for direction in Direction::ALL.iter().copied() {
+            assert_eq!(direction.opposite().opposite(), direction);
+            assert_eq!(direction.opposite().to_vector(), -direction.to_vector());
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for direction in Direction::ALL.iter().copied() {
+            assert_eq!(Direction::from_name(direction.name()), Some(direction));
+        }
+
+        assert_eq!(Direction::DownLeft.name(), "down_left");
+        assert_eq!(Direction::from_name("up_right"), Some(Direction::UpRight));
+        assert_eq!(Direction::from_name("upright"), None);
+        assert_eq!(Direction::from_name(""), None);
+    }
+
+    #[test]
+    fn diagonals() {
+        let diagonal: Vec<Direction> = Direction::ALL.iter().copied().filter(|direction| direction.is_diagonal()).collect();
+        assert_eq!(diagonal, vec![Direction::DownRight, Direction::DownLeft, Direction::UpLeft, Direction::UpRight]);
+
+        assert_eq!(Direction::UpLeft.horizontal(), Direction::Left);
+        assert_eq!(Direction::DownRight.horizontal(), Direction::Right);
+        assert_eq!(Direction::Up.horizontal(), Direction::Up);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::arena::Handle;
+
+// What every entity has, whatever its components. The entity itself is just a handle
+// into the world's arena of these.
+pub struct EntityInfo {
+    // the prefab it was spawned from, mostly useful when debugging
+    pub prefab: String,
+}
+
+pub type Entity = Handle<EntityInfo>;
+
+// One component type for every entity that has it, indexed by the entity's arena slot.
+// Each value remembers the full handle it was added for, so a handle to a despawned
+// entity never finds the component of whatever reused its slot.
+pub struct Storage<T> {
+    slots: Vec<Option<(Entity, T)>>,
+}
+
+impl<T> Storage<T> {
+    pub fn new() -> Storage<T> {
+        Storage { slots: Vec::new() }
+    }
+
+    pub fn insert(&mut self, entity: Entity, value: T) {
+        let index = entity.index();
+
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+
+        self.slots[index] = Some((entity, value));
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        match self.slots.get_mut(entity.index()) {
+            Some(slot) if slot.as_ref().is_some_and(|(owner, _)| *owner == entity) => {
+                slot.take().map(|(_, value)| value)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index()) {
+            Some(Some((owner, value))) if *owner == entity => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index()) {
+            Some(Some((owner, value))) if *owner == entity => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    // in slot order, the same order the entity arena iterates in
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().filter_map(|slot| slot.as_ref().map(|(entity, value)| (*entity, value)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().filter_map(|slot| slot.as_mut().map(|(entity, value)| (*entity, value)))
+    }
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Storage<T> {
+        Storage::new()
+    }
+}
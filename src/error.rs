@@ -0,0 +1,32 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// What can go wrong loading the game's data. Files that can't be read keep their io error,
+// files that can be read but make no sense are reported as `InvalidData` with the parse
+// error inside, so the message says which file and why either way.
+#[derive(Debug)]
+pub enum Error {
+    FailedToLoadAsset { reason: io::Error, path: PathBuf },
+}
+
+pub type Result<T = ()> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FailedToLoadAsset { reason, path } => {
+                write!(f, "failed to load asset from {}: {}", path.to_string_lossy(), reason)
+            },
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::FailedToLoadAsset { reason, .. } => Some(reason),
+        }
+    }
+}
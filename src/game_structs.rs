@@ -1,68 +1,303 @@
-use tetra::math::Vec2;
-use tetra::graphics::animation::Animation;
-use tetra::graphics::{Texture, Camera};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::util::{load_json, wrap_text};
 
 pub struct Tile {
-    pub texture: Texture,
+    pub id: i32,
     pub position: Vec2<f32>,
     pub collidable: bool,
+    pub damage: i32,
+    pub friction: f32,
+    pub layer: i32,
 }
 
-pub struct Player {
-    pub animation: Animation,
-    pub position: Vec2<f32>,
-    pub velocity_x: f32,
-    pub colliding: bool,
-    pub facing: i8,
-    pub prev_facing: i8,
-    pub alive: bool,
-}
-
-pub struct PlayerAttackSphere {
-    pub animation: Animation,
-    pub position: Vec2<f32>,
-    pub velocity: f32,
-    pub facing: i8,
+pub struct HelpMenu {
     pub visible: bool,
+    pub text: String,
 }
 
-pub struct Enemy {
-    pub animation: Animation,
-    pub position: Vec2<f32>,
-    pub velocity: f32,
-    pub range_end: f32,
-    pub range_start: f32,
-    pub facing: i8,
+// How dialogue is typed into the box. The box shows `rows` lines of `columns` characters
+// of the monospace dialogue font.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DialogueBoxConfig {
+    // 0 shows a whole page at once
+    pub chars_per_second: f32,
+    pub columns: usize,
+    pub rows: usize,
 }
 
-pub struct Npc {
-    pub animation: Animation,
-    pub position: Vec2<f32>,
+impl Default for DialogueBoxConfig {
+    fn default() -> DialogueBoxConfig {
+        DialogueBoxConfig {
+            chars_per_second: 40.0,
+            columns: 70,
+            rows: 6,
+        }
+    }
 }
 
-pub struct Help_Menu {
-    pub texture: Texture,
-    pub position: Vec2<f32>,
-    pub visible: bool,
-    pub text: String,
+impl DialogueBoxConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<DialogueBoxConfig> {
+        load_json(path)
+    }
 }
 
+// The box at the bottom of the screen dialogue is typed into. Text too long for it is
+// split into pages, the player turns them. `output_text` is what to draw this frame.
 pub struct DialogueBox {
-    pub texture: Texture,
-    pub position: Vec2<f32>,
     pub visible: bool,
     pub text: String,
     pub output_text: String,
-    pub text_wrap_finished: bool,
+    // what the player can answer, shown under the text once the last page is typed out
+    pub choices: Vec<String>,
+    pub selected: usize,
+    // more text follows after the box's pages, so it shows the "more" marker at the end
+    pub continues: bool,
+    pub config: DialogueBoxConfig,
+    pages: Vec<Vec<char>>,
+    page: usize,
+    // how much of the page has been typed, in characters
+    typed: f32,
+}
+
+impl HelpMenu {
+    pub fn new(
+        visible: bool,
+        text: String,
+    ) -> HelpMenu {
+        HelpMenu {
+            visible,
+            text,
+        }
+    }
 }
 
-pub struct GameState {
-    pub player: Player,
-    pub npc: Npc,
-    pub tiles: Vec<Tile>,
-    pub player_attack_instances: Vec<PlayerAttackSphere>,
-    pub enemy_instances: Vec<Enemy>,
-    pub camera: Camera,
-    pub help_menu: Help_Menu,
-    pub dialogue_box: DialogueBox,
+impl DialogueBox {
+    pub fn new(
+        visible: bool,
+        config: DialogueBoxConfig,
+    ) -> DialogueBox {
+        DialogueBox {
+            visible,
+            text: String::new(),
+            output_text: String::new(),
+            choices: Vec::new(),
+            selected: 0,
+            continues: false,
+            config,
+            pages: Vec::new(),
+            page: 0,
+            typed: 0.0,
+        }
+    }
+
+    // Starts typing `text` from its first page. The last page leaves room for `choices`.
+    pub fn open(&mut self, text: &str, choices: Vec<String>, continues: bool) {
+        let rows = self.config.rows.max(1);
+        let mut pages: Vec<Vec<String>> = wrap_text(text, self.config.columns)
+            .chunks(rows)
+            .map(|lines| lines.to_vec())
+            .collect();
+
+        let room = rows.saturating_sub(choices.len()).max(1);
+        if let Some(last) = pages.last_mut() {
+            if last.len() > room {
+                let overflow = last.split_off(room);
+                pages.push(overflow);
+            }
+        }
+
+        self.visible = true;
+        self.text = text.to_string();
+        self.pages = pages.iter().map(|lines| lines.join("\n").chars().collect()).collect();
+        self.page = 0;
+        self.typed = 0.0;
+        self.choices = choices;
+        self.selected = 0;
+        self.continues = continues;
+        self.output_text.clear();
+    }
+
+    pub fn close(&mut self) {
+        self.visible = false;
+        self.text.clear();
+        self.output_text.clear();
+        self.choices.clear();
+        self.pages.clear();
+    }
+
+    fn page_length(&self) -> usize {
+        self.pages.get(self.page).map_or(0, |page| page.len())
+    }
+
+    // Types more of the page, called every tick.
+    pub fn update(&mut self, dt: Duration) {
+        if !self.visible {
+            return;
+        }
+
+        let length = self.page_length() as f32;
+        self.typed = if self.config.chars_per_second <= 0.0 {
+            length
+        } else {
+            (self.typed + self.config.chars_per_second * dt.as_secs_f32()).min(length)
+        };
+
+        self.output_text = self.pages.get(self.page).map_or(String::new(), |page| page[..self.typed as usize].iter().collect());
+    }
+
+    pub fn typed_out(&self) -> bool {
+        self.typed as usize >= self.page_length()
+    }
+
+    pub fn last_page(&self) -> bool {
+        self.page + 1 >= self.pages.len()
+    }
+
+    // Skips to the end of the page if it is still being typed, else turns to the next one.
+    // Returns false when there was nothing left to show, so whatever comes after the box's
+    // text can happen.
+    pub fn advance(&mut self) -> bool {
+        if !self.typed_out() {
+            self.typed = self.page_length() as f32;
+        } else if !self.last_page() {
+            self.page += 1;
+            self.typed = 0.0;
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    // the choices once they can be picked, on the typed out last page
+    pub fn choices_shown(&self) -> bool {
+        self.last_page() && self.typed_out() && !self.choices.is_empty()
+    }
+
+    // whether to show the marker that says there is more to read
+    pub fn more(&self) -> bool {
+        self.typed_out() && (!self.last_page() || (self.continues && self.choices.is_empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialogue_box(columns: usize, rows: usize) -> DialogueBox {
+        let config = DialogueBoxConfig {
+            chars_per_second: 10.0,
+            columns,
+            rows,
+        };
+
+        DialogueBox::new(false, config)
+    }
+
+    fn type_out(dialogue_box: &mut DialogueBox) {
+        dialogue_box.update(Duration::from_secs(60));
+    }
+
+    #[test]
+    fn open_splits_long_text_into_pages() {
+        let mut dialogue_box = dialogue_box(10, 2);
+        dialogue_box.open("one two three four five six seven", Vec::new(), false);
+
+        assert!(dialogue_box.visible);
+        type_out(&mut dialogue_box);
+        assert_eq!(dialogue_box.output_text, "one two\nthree four");
+
+        assert!(dialogue_box.advance());
+        type_out(&mut dialogue_box);
+        assert_eq!(dialogue_box.output_text, "five six\nseven");
+        assert!(dialogue_box.last_page());
+    }
+
+    #[test]
+    fn the_last_page_leaves_room_for_choices() {
+        let mut dialogue_box = dialogue_box(10, 3);
+        dialogue_box.open("one two three four five six", vec![String::from("yes"), String::from("no")], false);
+
+        // three lines would fit a page on their own, but only one fits above two choices
+        type_out(&mut dialogue_box);
+        assert_eq!(dialogue_box.output_text, "one two");
+        assert!(!dialogue_box.last_page());
+
+        dialogue_box.advance();
+        type_out(&mut dialogue_box);
+        assert_eq!(dialogue_box.output_text, "three four\nfive six");
+        assert!(dialogue_box.last_page());
+    }
+
+    #[test]
+    fn advance_finishes_the_page_then_turns_it() {
+        let mut dialogue_box = dialogue_box(10, 1);
+        dialogue_box.open("hello there", Vec::new(), false);
+
+        dialogue_box.update(Duration::from_millis(300));
+        assert_eq!(dialogue_box.output_text, "hel");
+        assert!(!dialogue_box.typed_out());
+
+        // the first press shows the rest of the page
+        assert!(dialogue_box.advance());
+        dialogue_box.update(Duration::from_secs(0));
+        assert_eq!(dialogue_box.output_text, "hello");
+
+        // the next turns to the next page, which starts typing from nothing
+        assert!(dialogue_box.advance());
+        dialogue_box.update(Duration::from_secs(0));
+        assert_eq!(dialogue_box.output_text, "");
+
+        type_out(&mut dialogue_box);
+        assert!(!dialogue_box.advance());
+    }
+
+    #[test]
+    fn more_shows_while_there_is_more_to_read() {
+        let mut dialogue_box = dialogue_box(10, 1);
+        dialogue_box.open("hello there", Vec::new(), false);
+
+        assert!(!dialogue_box.more());
+        type_out(&mut dialogue_box);
+        assert!(dialogue_box.more());
+
+        dialogue_box.advance();
+        type_out(&mut dialogue_box);
+        assert!(!dialogue_box.more());
+
+        // text after the box's own, like the next node of a conversation
+        dialogue_box.open("hello", Vec::new(), true);
+        type_out(&mut dialogue_box);
+        assert!(dialogue_box.more());
+
+        // choices come next instead, they are shown rather than the marker
+        dialogue_box.open("hello", vec![String::from("bye")], true);
+        type_out(&mut dialogue_box);
+        assert!(!dialogue_box.more());
+    }
+
+    #[test]
+    fn choices_are_shown_once_the_last_page_is_typed_out() {
+        let mut dialogue_box = dialogue_box(10, 2);
+        dialogue_box.open("one two three four five", vec![String::from("yes")], false);
+
+        type_out(&mut dialogue_box);
+        assert!(!dialogue_box.choices_shown());
+
+        dialogue_box.advance();
+        assert!(!dialogue_box.choices_shown());
+
+        type_out(&mut dialogue_box);
+        assert!(dialogue_box.choices_shown());
+
+        dialogue_box.open("hello", Vec::new(), false);
+        type_out(&mut dialogue_box);
+        assert!(!dialogue_box.choices_shown());
+    }
 }
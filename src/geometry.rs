@@ -0,0 +1,24 @@
+use std::iter;
+
+// An axis-aligned rectangle in pixels, `x` and `y` being the top left corner. Used for
+// views of the world and for regions of textures.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Rectangle {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rectangle {
+    pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
+        Rectangle { x, y, width, height }
+    }
+
+    // Rectangles side by side going right from `x`, `y`, for slicing a row of a sprite sheet.
+    pub fn row(x: f32, y: f32, width: f32, height: f32) -> impl Iterator<Item = Rectangle> {
+        iter::successors(Some(Rectangle::new(x, y, width, height)), |previous| {
+            Some(Rectangle::new(previous.x + previous.width, previous.y, previous.width, previous.height))
+        })
+    }
+}
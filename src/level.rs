@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use vek::Vec2;
+
+use crate::tiled;
+use crate::tiles::TileRegistry;
+use crate::util::load_asset;
+
+pub const TILE_SIZE: f32 = 32.0;
+pub const MAX_LEVEL_SIZE: usize = 512;
+
+pub struct TileLayer {
+    pub name: String,
+    pub tiles: Vec<i32>,
+}
+
+// An entity placed in the level, `prefab` names its entry in the prefab file.
+pub struct Spawn {
+    pub prefab: String,
+    pub position: Vec2<f32>,
+}
+
+pub struct Level {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub layers: Vec<TileLayer>,
+    pub player_spawn: Vec2<f32>,
+    // everything else that is spawned when the level starts
+    pub spawns: Vec<Spawn>,
+    // per tile id, set by tile properties in imported Tiled maps
+    pub collidable_overrides: HashMap<i32, bool>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    MissingSize,
+    MissingPlayerSpawn,
+    InvalidSize { line: usize, width: usize, height: usize },
+    UnknownTile { line: usize, column: usize, id: i32 },
+    RaggedRow { line: usize, expected: usize, found: usize },
+    WrongRowCount { layer: String, expected: usize, found: usize },
+    UnterminatedLayer { layer: String },
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::MissingSize => write!(f, "level has no `size` line"),
+            LevelError::MissingPlayerSpawn => write!(f, "level has no `spawn player` line"),
+            LevelError::InvalidSize { line, width, height } => write!(
+                f,
+                "line {}: size {}x{} is out of range (1 to {} tiles per side)",
+                line, width, height, MAX_LEVEL_SIZE
+            ),
+            LevelError::UnknownTile { line, column, id } => {
+                write!(f, "line {}, column {}: unknown tile id {}", line, column, id)
+            }
+            LevelError::RaggedRow { line, expected, found } => write!(
+                f,
+                "line {}: expected {} tiles in row, found {}",
+                line, expected, found
+            ),
+            LevelError::WrongRowCount { layer, expected, found } => write!(
+                f,
+                "layer `{}`: expected {} rows, found {}",
+                layer, expected, found
+            ),
+            LevelError::UnterminatedLayer { layer } => {
+                write!(f, "layer `{}` is missing its `end` line", layer)
+            }
+            LevelError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl Level {
+    pub fn tile_at(&self, layer: usize, x: usize, y: usize) -> i32 {
+        self.layers[layer].tiles[y * self.width + x]
+    }
+
+    pub fn collidable(&self, id: i32, default: bool) -> bool {
+        self.collidable_overrides.get(&id).copied().unwrap_or(default)
+    }
+
+    // .tmx and .tmj files are imported from Tiled, anything else is read as a level file
+    pub fn load<P: AsRef<Path>>(path: P, registry: &TileRegistry) -> crate::Result<Level> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tmx") | Some("tmj") => return tiled::load(path, registry),
+            _ => {}
+        }
+
+        load_asset(path, |source| Level::parse(source, registry))
+    }
+
+    pub fn parse(source: &str, registry: &TileRegistry) -> Result<Level, LevelError> {
+        let mut name = String::new();
+        let mut size: Option<(usize, usize)> = None;
+        let mut layers: Vec<TileLayer> = Vec::new();
+        let mut player_spawn: Option<Vec2<f32>> = None;
+        let mut spawns: Vec<Spawn> = Vec::new();
+
+        // the layer currently being read, if any
+        let mut current_layer: Option<TileLayer> = None;
+        let mut current_rows = 0;
+
+        for (index, raw_line) in source.lines().enumerate() {
+            let line = index + 1;
+            let text = raw_line.trim();
+
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            if let Some(mut layer) = current_layer.take() {
+                let (width, height) = size.unwrap();
+
+                if text == "end" {
+                    if current_rows != height {
+                        return Err(LevelError::WrongRowCount {
+                            layer: layer.name,
+                            expected: height,
+                            found: current_rows,
+                        });
+                    }
+
+                    layers.push(layer);
+                    continue;
+                }
+
+                let cells: Vec<&str> = text
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|cell| !cell.is_empty())
+                    .collect();
+
+                if cells.len() != width {
+                    return Err(LevelError::RaggedRow {
+                        line,
+                        expected: width,
+                        found: cells.len(),
+                    });
+                }
+
+                if current_rows == height {
+                    return Err(LevelError::WrongRowCount {
+                        layer: layer.name,
+                        expected: height,
+                        found: current_rows + 1,
+                    });
+                }
+
+                for (column, cell) in cells.iter().enumerate() {
+                    let id: i32 = cell.parse().map_err(|_| LevelError::Syntax {
+                        line,
+                        message: format!("`{}` is not a tile id", cell),
+                    })?;
+
+                    if !registry.is_known(id) {
+                        return Err(LevelError::UnknownTile {
+                            line,
+                            column: column + 1,
+                            id,
+                        });
+                    }
+
+                    layer.tiles.push(id);
+                }
+
+                current_rows += 1;
+                current_layer = Some(layer);
+                continue;
+            }
+
+            let words: Vec<&str> = text.split_whitespace().collect();
+
+            match words[0] {
+                "name" => {
+                    name = text["name".len()..].trim().to_string();
+                }
+                "size" => {
+                    // layers are read against the size they were started with
+                    if size.is_some() || !layers.is_empty() {
+                        return Err(LevelError::Syntax {
+                            line,
+                            message: String::from("`size` must appear once, before any layer"),
+                        });
+                    }
+
+                    let width = parse_number(&words, 1, line)?;
+                    let height = parse_number(&words, 2, line)?;
+
+                    if width == 0 || height == 0 || width > MAX_LEVEL_SIZE || height > MAX_LEVEL_SIZE {
+                        return Err(LevelError::InvalidSize { line, width, height });
+                    }
+
+                    size = Some((width, height));
+                }
+                "spawn" => {
+                    let kind = words.get(1).copied().unwrap_or("");
+                    let position = Vec2::new(
+                        parse_coordinate(&words, 2, line)?,
+                        parse_coordinate(&words, 3, line)?,
+                    );
+
+                    match kind {
+                        "player" if player_spawn.is_some() => {
+                            return Err(LevelError::Syntax {
+                                line,
+                                message: String::from("the player can only spawn once"),
+                            })
+                        }
+                        "player" => player_spawn = Some(position),
+                        "" => {
+                            return Err(LevelError::Syntax {
+                                line,
+                                message: String::from("`spawn` needs a prefab name"),
+                            })
+                        }
+                        prefab => spawns.push(Spawn {
+                            prefab: prefab.to_string(),
+                            position,
+                        }),
+                    }
+                }
+                "layer" => {
+                    if size.is_none() {
+                        return Err(LevelError::MissingSize);
+                    }
+
+                    current_layer = Some(TileLayer {
+                        name: words.get(1).copied().unwrap_or("ground").to_string(),
+                        tiles: Vec::new(),
+                    });
+                    current_rows = 0;
+                }
+                other => {
+                    return Err(LevelError::Syntax {
+                        line,
+                        message: format!("unknown directive `{}`", other),
+                    })
+                }
+            }
+        }
+
+        if let Some(layer) = current_layer {
+            return Err(LevelError::UnterminatedLayer { layer: layer.name });
+        }
+
+        let (width, height) = size.ok_or(LevelError::MissingSize)?;
+        let player_spawn = player_spawn.ok_or(LevelError::MissingPlayerSpawn)?;
+
+        Ok(Level {
+            name,
+            width,
+            height,
+            layers,
+            player_spawn,
+            spawns,
+            collidable_overrides: HashMap::new(),
+        })
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(words: &[&str], index: usize, line: usize) -> Result<T, LevelError> {
+    let word = words.get(index).ok_or_else(|| LevelError::Syntax {
+        line,
+        message: format!("`{}` is missing a value", words[0]),
+    })?;
+
+    word.parse().map_err(|_| LevelError::Syntax {
+        line,
+        message: format!("`{}` is not a valid number", word),
+    })
+}
+
+// `f32` also parses `NaN` and `inf`, which are no place to put anything
+fn parse_coordinate(words: &[&str], index: usize, line: usize) -> Result<f32, LevelError> {
+    let value: f32 = parse_number(words, index, line)?;
+
+    if !value.is_finite() {
+        return Err(LevelError::Syntax {
+            line,
+            message: format!("`{}` is not a finite position", words[index]),
+        });
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> TileRegistry {
+        let source = r#"{ "tiles": [{ "id": 1, "name": "stone", "texture": "stone.png", "collidable": true }] }"#;
+
+        TileRegistry::parse(source).unwrap()
+    }
+
+    fn parse(source: &str) -> Result<Level, LevelError> {
+        Level::parse(source, &registry())
+    }
+
+    #[test]
+    fn parses_a_level() {
+        let source = "name Test\nsize 2 2\nspawn player 32 32\nspawn slime 64 0\nlayer\n1 0\n0, 1\nend\n";
+        let level = parse(source).unwrap();
+
+        assert_eq!(level.name, "Test");
+        assert_eq!((level.width, level.height), (2, 2));
+        assert_eq!(level.player_spawn, Vec2::new(32.0, 32.0));
+        assert_eq!(level.spawns.len(), 1);
+        assert_eq!(level.layers[0].name, "ground");
+        assert_eq!(level.layers[0].tiles, vec![1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn missing_size() {
+        assert!(matches!(parse("spawn player 0 0\n"), Err(LevelError::MissingSize)));
+        assert!(matches!(parse("spawn player 0 0\nlayer\n1\nend\n"), Err(LevelError::MissingSize)));
+    }
+
+    #[test]
+    fn missing_player_spawn() {
+        assert!(matches!(parse("size 1 1\n"), Err(LevelError::MissingPlayerSpawn)));
+    }
+
+    #[test]
+    fn invalid_size() {
+        assert!(matches!(parse("size 0 4\n"), Err(LevelError::InvalidSize { line: 1, width: 0, height: 4 })));
+        assert!(matches!(parse("size 4 513\n"), Err(LevelError::InvalidSize { height: 513, .. })));
+    }
+
+    #[test]
+    fn unknown_tile() {
+        let error = parse("size 2 1\nspawn player 0 0\nlayer\n1 7\nend\n");
+        assert!(matches!(error, Err(LevelError::UnknownTile { line: 4, column: 2, id: 7 })));
+    }
+
+    #[test]
+    fn ragged_row() {
+        let error = parse("size 2 2\nspawn player 0 0\nlayer\n1 1\n1\nend\n");
+        assert!(matches!(error, Err(LevelError::RaggedRow { line: 5, expected: 2, found: 1 })));
+    }
+
+    #[test]
+    fn wrong_row_count() {
+        let short = parse("size 1 2\nspawn player 0 0\nlayer walls\n1\nend\n");
+        assert!(matches!(
+            short,
+            Err(LevelError::WrongRowCount { ref layer, expected: 2, found: 1 }) if layer == "walls"
+        ));
+
+        let long = parse("size 1 1\nspawn player 0 0\nlayer\n1\n1\nend\n");
+        assert!(matches!(long, Err(LevelError::WrongRowCount { expected: 1, found: 2, .. })));
+    }
+
+    #[test]
+    fn unterminated_layer() {
+        let error = parse("size 1 1\nspawn player 0 0\nlayer top\n1\n");
+        assert!(matches!(error, Err(LevelError::UnterminatedLayer { ref layer }) if layer == "top"));
+    }
+
+    #[test]
+    fn syntax() {
+        let bad = [
+            "size 1\n",
+            "size one 1\n",
+            "size 1 1\nspawn\n",
+            "size 1 1\nspawn 0 0\n",
+            "size 1 1\nwarp 0 0\n",
+            "size 1 1\nlayer\nx\nend\n",
+            // `size` only once, and before the layers it describes
+            "size 1 1\nsize 2 2\n",
+            "size 1 1\nspawn player 0 0\nlayer\n1\nend\nsize 2 2\n",
+        ];
+
+        for source in bad.iter() {
+            assert!(matches!(parse(source), Err(LevelError::Syntax { .. })), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn duplicate_player_spawn() {
+        let error = parse("size 1 1\nspawn player 0 0\nspawn player 32 32\n");
+        assert!(matches!(error, Err(LevelError::Syntax { line: 3, .. })));
+    }
+
+    #[test]
+    fn spawn_position_not_finite() {
+        for position in ["NaN 0", "0 inf", "-inf 0", "0 nan"].iter() {
+            let source = format!("size 1 1\nspawn player 0 0\nspawn slime {}\n", position);
+            assert!(matches!(parse(&source), Err(LevelError::Syntax { line: 3, .. })), "{:?}", position);
+        }
+    }
+}
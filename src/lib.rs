@@ -0,0 +1,31 @@
+// The simulation side of Hypoterra. Nothing in here needs a window, a graphics context
+// or an audio device, so the game can be stepped headless from tests. The `hypoterra`
+// binary is the Tetra front-end that reads input, calls `World::update` and draws.
+
+pub mod ai;
+pub mod animation;
+pub mod arena;
+pub mod camera;
+pub mod components;
+pub mod controls;
+pub mod dialogue;
+pub mod direction;
+pub mod ecs;
+pub mod error;
+pub mod game_structs;
+pub mod geometry;
+pub mod level;
+pub mod movement;
+pub mod pathfinding;
+pub mod physics;
+pub mod prefabs;
+pub mod spritesheet;
+pub mod systems;
+pub mod tiled;
+pub mod tilemap;
+pub mod tiles;
+pub mod timestep;
+pub mod util;
+pub mod world;
+
+pub use error::{Error, Result};
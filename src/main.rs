@@ -1,688 +1,261 @@
-mod level1;
-mod util;
-mod game_structs;
-
-use tetra::graphics::{self, Color, Texture, Rectangle, Camera, Text, Font};
-use tetra::graphics::animation::Animation;
-use tetra::{Context, ContextBuilder, State};
-use tetra::input::{self, Key};
+mod assets;
+mod atlas;
+mod scenes;
+mod sprite;
+
+use tetra::graphics::{self, Camera, Texture, Text};
+use tetra::input::{self, GamepadButton, GamepadStick, Key};
+use tetra::time::{self, Timestep};
+use tetra::{Context, ContextBuilder, Event, TetraError};
 use tetra::math::Vec2;
+use tetra::window;
+use std::io;
 use std::time::Duration;
-// use std::{thread, time};
-// use tetra::window;
 
-use util::{collision, in_camera_viewport, in_camera_viewport_attack};
-use game_structs::{Tile, Player, PlayerAttackSphere, Enemy, GameState, Npc, Help_Menu, DialogueBox};
+use hypoterra::controls::Input;
+use hypoterra::level::Level;
+use hypoterra::movement::{apply_dead_zone, MovementConfig};
+use hypoterra::systems::sprites_to_draw;
+use hypoterra::tilemap::{tiles_from_level, TileChunks};
+use hypoterra::timestep::{interpolate, tick_duration, TICKS_PER_SECOND};
+use hypoterra::util::camera_view;
+use hypoterra::world::{World, WorldConfig};
+use assets::Assets;
+use atlas::{TileAtlas, TileMapRenderer};
+use scenes::{pause_pressed, GameOverScene, PauseScene, Scene, SceneStack, TitleScene, Transition, GAME_OVER_DELAY};
+use sprite::draw_sprite;
 
 const WINDOW_WIDTH: f32 = 1280.0;
 const WINDOW_HEIGHT: f32 = 960.0;
 
-impl Tile {
-    fn new(
-        texture: Texture,
-        position: Vec2<f32>,
-        collidable: bool,
-    ) -> Tile {
-        Tile {
-            texture,
-            position,
-            collidable,
-        }
-    }
+// The gameplay scene: turns keyboard and gamepad state into an `Input` for the
+// simulation and draws whatever the `World` looks like.
+struct GameState {
+    world: World,
+    tilemap: TileMapRenderer,
+    help_menu_texture: Texture,
+    dialogue_box_texture: Texture,
+    // how long ago the player died for the last time
+    game_over_for: Duration,
 }
 
-impl Player {
-    fn new(
-        animation: Animation,
-        position: Vec2<f32>,
-        velocity_x: f32,
-        colliding: bool,
-        facing: i8,
-        prev_facing: i8,
-        alive: bool,
-    ) -> Player {
-        Player {
-            animation,
-            position,
-            velocity_x,
-            colliding,
-            facing,
-            prev_facing,
-            alive,
-        }
-    }
-}
+impl GameState {
+    fn new(ctx: &mut Context, assets: &mut Assets) -> tetra::Result<GameState>{
+
+        let config = WorldConfig::load("./resources", WINDOW_WIDTH, WINDOW_HEIGHT).map_err(asset_error)?;
+
+        // every sprite sheet is loaded up front so drawing never waits on the disk
+        let sprite_sheets: Vec<&str> = config
+            .animations
+            .sets()
+            .flat_map(|set| set.clips())
+            .map(|clip| clip.texture.as_str())
+            .collect();
+        assets.preload_textures(ctx, &sprite_sheets)?;
+        assets.preload_textures(ctx, &[
+            "./resources/help_menu.png",
+            "./resources/dialogue_box.png",
+        ])?;
+        assets.font(ctx, "./resources/prstart.ttf")?;
+
+        let help_menu_texture = assets.texture(ctx, "./resources/help_menu.png")?;
+        let dialogue_box_texture = assets.texture(ctx, "./resources/dialogue_box.png")?;
+
+        let level_path = "./resources/levels/level1.lvl";
+        let level = Level::load(level_path, &config.tile_registry).map_err(asset_error)?;
+        window::set_title(ctx, format!("HYPOTERRA - {}", level.name));
+
+        let tilemap = TileMapRenderer::new(
+            TileAtlas::build(ctx, &config.tile_registry)?,
+            TileChunks::build(&tiles_from_level(&level, &config.tile_registry), level.width, level.height),
+        );
+        let world = World::new(&level, config)
+            .map_err(|error| TetraError::FailedToLoadAsset {
+                reason: io::Error::new(io::ErrorKind::InvalidData, error),
+                path: level_path.into(),
+            })?;
 
-impl Npc {
-    fn new(
-        animation: Animation,
-        position: Vec2<f32>,
-    ) -> Npc {
-        Npc {
-            animation,
-            position,
-        }
+        Ok(GameState {
+            world,
+            tilemap,
+            help_menu_texture,
+            dialogue_box_texture,
+            game_over_for: Duration::from_secs(0),
+        })
     }
 }
 
-impl PlayerAttackSphere {
-    fn new(
-        animation: Animation,
-        position: Vec2<f32>,
-        velocity: f32,
-        facing: i8,
-        visible: bool,
-    ) -> PlayerAttackSphere {
-        PlayerAttackSphere{
-            animation,
-            position,
-            velocity,
-            facing,
-            visible,
-        }
+// The simulation has its own error type so it doesn't depend on Tetra, loading errors are
+// handed to Tetra the same way its own are.
+fn asset_error(error: hypoterra::Error) -> TetraError {
+    match error {
+        hypoterra::Error::FailedToLoadAsset { reason, path } => TetraError::FailedToLoadAsset { reason, path },
     }
 }
 
-impl Enemy {
-    fn new(
-        animation: Animation,
-        position: Vec2<f32>,
-        velocity: f32,
-        range_end: f32,
-        range_start: f32,
-        facing: i8,
-    ) -> Enemy {
-        Enemy {
-            animation,
-            position,
-            velocity,
-            range_end,
-            range_start,
-            facing,
-        }
+// This tick's input from the keyboard and the first gamepad.
+fn read_input(ctx: &Context, config: &MovementConfig) -> Input {
+    Input {
+        movement: read_move_input(ctx, config),
+        attack: input::is_key_pressed(ctx, Key::Space),
+        interact: input::is_key_pressed(ctx, Key::T),
+        menu_up: input::is_key_pressed(ctx, Key::W) || input::is_key_pressed(ctx, Key::Up) ||
+            input::is_gamepad_button_pressed(ctx, 0, GamepadButton::Up),
+        menu_down: input::is_key_pressed(ctx, Key::S) || input::is_key_pressed(ctx, Key::Down) ||
+            input::is_gamepad_button_pressed(ctx, 0, GamepadButton::Down),
     }
 }
 
-impl Help_Menu {
-    fn new(
-        texture: Texture,
-        position: Vec2<f32>,
-        visible: bool,
-        text: String,
-    ) -> Help_Menu {
-        Help_Menu {
-            texture,
-            position,
-            visible,
-            text,
-        }
-    }
-}
+// The direction the player wants to move in, with a length between 0 and 1. Keyboard and
+// d-pad input is digital, the left stick of the first gamepad is analog.
+fn read_move_input(ctx: &Context, config: &MovementConfig) -> Vec2<f32> {
+    let mut digital: Vec2<f32> = Vec2::zero();
 
-impl DialogueBox {
-    fn new(
-        texture: Texture,
-        position: Vec2<f32>,
-        visible: bool,
-        text: String,
-        output_text: String,
-        text_wrap_finished: bool,
-    ) -> DialogueBox {
-        DialogueBox {
-            texture,
-            position,
-            visible,
-            text,
-            output_text,
-            text_wrap_finished,
-        }
+    if input::is_key_down(ctx, Key::A) || input::is_gamepad_button_down(ctx, 0, GamepadButton::Left) {
+        digital.x -= 1.0;
     }
-}
-
-
-impl GameState {
-    fn new(ctx: &mut Context) -> tetra::Result<GameState>{
-
-        let quarter_second = Duration::from_millis(250);
-        let twentieth_second = Duration::from_millis(50);
-
-        let player_texture = Texture::new(ctx, "./resources/sorcerer_idle_down.png")?;
-        let player_animation = Animation::new(
-            player_texture,
-            Rectangle::row(0.0, 0.0, 48.0, 48.0).take(2).collect(),
-            quarter_second,
-        );
-        let player_position = Vec2::new (
-            WINDOW_WIDTH / 2.0 - 48.0 / 2.0,
-            WINDOW_HEIGHT / 2.0 - 48.0 as f32 / 2.0
-        );
-        let player_velocity_x = 0.0;
-        let player_colliding = false;
-        // 0: sorcerer_idle, facing none
-        // 1: sorcerer_walking_right, facing right
-        // 2: sorcerer_walking_left, facing left
-        // 3: sorcerer_walking_up, facing up
-        // 4: sorcerer_walking_down, facing down
-        let player_facing = 0;
-        let player_prev_facing = 4;
-        let player_alive = true;
-
-        let npc_texture = Texture::new(ctx, "./resources/scientist_idle.png")?;
-        let npc_animation = Animation::new(
-            npc_texture,
-            Rectangle::row(0.0, 0.0, 48.0, 48.0).take(2).collect(),
-            quarter_second,
-        );
-        let npc_position = Vec2::new (
-            650.0,
-            200.0,
-        );
-
-        let help_menu_texture = Texture::new(ctx, "./resources/help_menu.png")?;
-        // this will change in the update function
-        let help_menu_position = Vec2::new(
-            0.0,
-            0.0,
-        );
-        let help_menu_visible = false;
-        let help_menu_text = String::new();
-
-        let dialogue_box_texture = Texture::new(ctx, "./resources/dialogue_box.png")?;
-        let dialogue_box_position = Vec2::new(
-            0.0,
-            0.0,
-        );
-        let dialogue_box_visible = false;
-        let dialogue_box_text = String::new();
-        let dialogue_box_output_text = String::from(":");
-        let dialogue_box_text_wrap_finished = false;
-
-        let player_attack_instances: Vec<PlayerAttackSphere> = Vec::new();
-
-        let mut tiles: Vec<Tile> = Vec::new();
-
-        let enemy_positions: Vec<Vec2<f32>> = vec![Vec2::new(960.0, 320.0), Vec2::new(896.0, 512.0)];
-
-        let mut enemies: Vec<Enemy> = Vec::new();
-
-        for pos in enemy_positions {
-            let enemy_texture = Texture::new(ctx, "./resources/beer_idle.png")?;
-            let enemy_animation = Animation::new(
-                enemy_texture,
-                Rectangle::row(0.0, 0.0, 48.0, 48.0).take(24).collect(),
-                twentieth_second,
-            );
-            let enemy_position = pos;
-            let enemy_velocity = 3.0;
-            let enemy_range_end = &enemy_position.x - 200.0;
-            let enemy_range_start = &enemy_position.x + 5.0;
-            let enemy_facing = 0;
-
-            enemies.push(Enemy::new(
-                enemy_animation,
-                enemy_position,
-                enemy_velocity,
-                enemy_range_end,
-                enemy_range_start,
-                enemy_facing,
-            ));
-        }
-
-        for (y, row) in level1::TILEMAP.iter_mut().enumerate() {
-            for (x, col) in row.iter_mut().enumerate() {
-                let mut stone = 1;
-                let mut woodplank = 2;
-                let mut grass = 4;
-
-                if col == &mut stone {
-                    let tile_texture = Texture::new(ctx, "./resources/stone_tile.png")?;
-                    let tile_position = Vec2::new (
-                        x as f32 * 32.0,
-                        y as f32 * 32.0,
-                    );
-
-                    tiles.push(Tile::new(tile_texture, tile_position, true));
-                } else if col == &mut woodplank {
-                    let tile_texture = Texture::new(ctx, "./resources/woodplank_tile.png")?;
-                    let tile_position = Vec2::new (
-                        x as f32 * 32.0,
-                        y as f32 * 32.0,
-                    );
-
-                    tiles.push(Tile::new(tile_texture, tile_position, false));
-                } else if col == &mut grass {
-                    let tile_texture = Texture::new(ctx, "./resources/grass_tile.png")?;
-                    let tile_position = Vec2::new (
-                        x as f32 * 32.0,
-                        y as f32 * 32.0,
-                    );
-
-                    tiles.push(Tile::new(tile_texture, tile_position, false));
-                } else {
-
-                }
-            }
-        }
-
-        Ok(GameState {
-            player: Player::new(
-                player_animation,
-                player_position,
-                player_velocity_x,
-                player_colliding,
-                player_facing,
-                player_prev_facing,
-                player_alive,
-            ),
-            npc: Npc::new(npc_animation, npc_position),
-            tiles: tiles,
-            player_attack_instances: player_attack_instances,
-            enemy_instances: enemies,
-            camera: Camera::with_window_size(ctx),
-            help_menu: Help_Menu::new(
-                help_menu_texture,
-                help_menu_position,
-                help_menu_visible,
-                help_menu_text,
-            ),
-            dialogue_box: DialogueBox::new(
-                dialogue_box_texture,
-                dialogue_box_position,
-                dialogue_box_visible,
-                dialogue_box_text,
-                dialogue_box_output_text,
-                dialogue_box_text_wrap_finished,
-            ),
-        })
+    if input::is_key_down(ctx, Key::D) || input::is_gamepad_button_down(ctx, 0, GamepadButton::Right) {
+        digital.x += 1.0;
+    }
+    if input::is_key_down(ctx, Key::W) || input::is_gamepad_button_down(ctx, 0, GamepadButton::Up) {
+        digital.y -= 1.0;
+    }
+    if input::is_key_down(ctx, Key::S) || input::is_gamepad_button_down(ctx, 0, GamepadButton::Down) {
+        digital.y += 1.0;
     }
-}
-
-impl State for GameState {
-    fn update(&mut self, ctx: &mut Context) -> tetra::Result {
-
-        // self.player.position.x < tile.position.x + (tile.texture.width() as f32) &&
-        // self.player.position.x + (48.0) > tile.position.x &&
-        // self.player.position.y < (tile.position.y + tile.texture.height() as f32) &&
-        // self.player.position.y + (48.0) > tile.position.y
-        if self.player.alive == true {
-            self.camera.position.x = self.player.position.x + 24.0;
-            self.camera.position.y = self.player.position.y + 24.0;
-            self.camera.update();
-        }
-
-        if collision(self.player.position, self.npc.position, 48.0, 48.0, 48.0, 48.0) == true && self.dialogue_box.visible == false {
-            self.help_menu.visible = true;
-            self.help_menu.text = String::from("Press T to talk.");
-
-            if input::is_key_pressed(ctx, Key::T) {
-                self.dialogue_box.visible = true;
-                self.dialogue_box.text = String::from("Hi! Welcome to HYPOTERRA, the land that may never see the light of day. Would you like to learn of the HYPOTERRA prophecy?");
-            }
-        } else {
-            self.help_menu.visible = false;
-        }
-
-        if self.help_menu.visible == true {
-            self.help_menu.position.x = self.camera.position.x - 590.0;
-            self.help_menu.position.y = self.camera.position.y + 360.0;
-        }
-
-        if self.dialogue_box.visible == true {
-            self.dialogue_box.position.x = self.camera.position.x - 440.0;
-            self.dialogue_box.position.y = self.camera.position.y + 260.0;
-        }
-
-        for tile in &self.tiles {
-            if collision(self.player.position, tile.position, 48.0, 48.0, 32.0, 32.0) == true && tile.collidable == true {
-
-                if self.player.facing == 2 {
-                    self.player.colliding = true;
-                    self.player.position.x = self.player.position.x + 7.0;
-                } else if self.player.facing == 1 {
-                    self.player.colliding = true;
-                    self.player.position.x = self.player.position.x - 7.0;
-                } else if self.player.facing == 3 {
-                    self.player.colliding = true;
-                    self.player.position.y = self.player.position.y + 7.0;
-                } else if self.player.facing == 4 {
-                    self.player.colliding = true;
-                    self.player.position.y = self.player.position.y - 7.0;
-                }
-
-
-                break;
-            } else {
-                self.player.colliding = false;
-            }
-
-            let mut index = 0;
-            for attack in &mut self.player_attack_instances {
-                if collision(
-                    attack.position,
-                    tile.position,
-                    32.0,
-                    32.0,
-                    32.0,
-                    32.0,) == true &&
-                    tile.collidable == true ||
-                    in_camera_viewport_attack(&self.camera, attack) == false {
-
-                        // The commented code was a bug but it might be an interesting concept to add
-                        // later on in the game
-                        // // self.tiles.remove(index);
-
-                        self.player_attack_instances.remove(index);
-
-                        break;
-                } else {
-                    index += 1;
-                }
-            }
-        }
-
-        // attack remover
-        for attack in self.player_attack_instances.iter() {
-            if attack.visible == false {
-                let index_2 = self.player_attack_instances.iter().position(|r| r.position == attack.position).unwrap();
-
-                &self.player_attack_instances.remove(index_2);
-
-                break;
-            } else {
-                //continue
-            }
-        }
-
-        // let attacks = &self.player_attack_instances;
-        // let mut enemies = &self.enemy_instances;
-        for mut attack in &mut self.player_attack_instances {
-            for enemy in &self.enemy_instances {
-                // enemy collision with attack instance
-                if collision(attack.position, enemy.position, 32.0, 32.0, 48.0, 48.0) == true &&
-                enemy.position.x < self.camera.viewport_width as f32 &&
-                enemy.position.y < self.camera.viewport_height as f32 &&
-                enemy.position.x > (self.camera.viewport_width as f32) - (self.camera.viewport_width as f32) &&
-                enemy.position.y > (self.camera.viewport_height as f32) - (self.camera.viewport_height as f32) {
-                    let index_1 = self.enemy_instances.iter().position(|r| r.position == enemy.position).unwrap();
-
-                    &self.enemy_instances.remove(index_1);
-                    attack.visible = false;
-
-                    break;
-                }
-            }
-
-            // can put more collision detection here
-        }
-
-        for enemy in &self.enemy_instances {
-            if collision(self.player.position, enemy.position, 48.0, 48.0, 48.0, 48.0) == true {
-                self.player.alive = false;
-            }
-        }
-
-        for mut enemy in &mut self.enemy_instances {
-            if enemy.position.x < enemy.range_end && enemy.facing == 0 {
-                enemy.facing = 1;
-                enemy.position.x += enemy.velocity;
-            } else if enemy.position.x > enemy.range_end && enemy.position.x < enemy.range_start && enemy.facing == 0 {
-                enemy.facing = 0;
-                enemy.position.x -= enemy.velocity;
-            } else if enemy.position.x > enemy.range_end && enemy.position.x < enemy.range_start && enemy.facing == 1 {
-                enemy.facing = 1;
-                enemy.position.x += enemy.velocity;
-            } else if enemy.position.x > enemy.range_start && enemy.facing == 1 {
-                enemy.facing = 0;
-                enemy.position.x -= enemy.velocity;
-            } else if enemy.position.x > enemy.range_start && enemy.facing == 0 {
-                enemy.facing = 0;
-                enemy.position.x -= enemy.velocity;
-            }
-        }
 
-        // Attack Instance Loop
-        for mut attack in &mut self.player_attack_instances {
-            if attack.facing == 1 {
-                attack.position.x += attack.velocity;
-            } else if attack.facing == 2 {
-                attack.position.x += attack.velocity;
-            } else if attack.facing == 3 {
-                attack.position.y += attack.velocity;
-            } else if attack.facing == 4 {
-                attack.position.y += attack.velocity;
-            }
+    if digital != Vec2::zero() {
+        // diagonals are as fast as straight lines
+        return digital.normalized();
+    }
 
-        }
+    apply_dead_zone(input::get_gamepad_stick_position(ctx, 0, GamepadStick::LeftStick), config.gamepad_dead_zone)
+}
 
-        // Move Left
-        if input::is_key_down(ctx, Key::A) && self.player.colliding == false {
-            self.player.velocity_x = -6.0;
-            self.player.position.x += self.player.velocity_x;
-            self.player.facing = 2;
-
-            self.player.prev_facing = self.player.facing;
-        } else if input::is_key_down(ctx, Key::D) && self.player.colliding == false {
-            self.player.velocity_x = 6.0;
-            self.player.position.x += self.player.velocity_x;
-            self.player.facing  = 1;
-
-            self.player.prev_facing = self.player.facing;
-        } else if input::is_key_down(ctx, Key::W) && self.player.colliding == false {
-            self.player.velocity_x = 6.0;
-            self.player.position.y -= self.player.velocity_x;
-            self.player.facing = 3;
-
-            self.player.prev_facing = self.player.facing;
-        } else if input::is_key_down(ctx, Key::S) && self.player.colliding == false {
-            self.player.velocity_x = 6.0;
-            self.player.position.y += self.player.velocity_x;
-            self.player.facing = 4;
-
-            self.player.prev_facing = self.player.facing;
-        } else {
-            self.player.prev_facing = self.player.prev_facing;
-            self.player.facing = 0;
+impl Scene for GameState {
+    fn update(&mut self, ctx: &mut Context, _assets: &mut Assets) -> tetra::Result<Transition> {
+        if pause_pressed(ctx) {
+            return Ok(Transition::Push(Box::new(PauseScene)));
         }
 
-        // Attack input handling
-        if input::is_key_pressed(ctx, Key::Space) && self.player_attack_instances.len() < 6 && self.player.alive == true {
-            let tenth_second = Duration::from_millis(100);
-            let attack_sphere_texture = Texture::new(ctx, "./resources/attack_ball.png")?;
-            let attack_sphere_animation = Animation::new(
-                attack_sphere_texture,
-                Rectangle::row(0.0, 0.0, 32.0, 32.0).take(2).collect(),
-                tenth_second,
-            );
-            let attack_sphere_position = Vec2::new (
-                self.player.position.x + (48.0 / 4.0),
-                self.player.position.y + (48.0 / 4.0),
-            );
-
-            let mut attack_sphere_velocity = 0.0;
-
-            let mut attack_sphere_facing: i8 = 1;
-
-            let attack_sphere_visible: bool = true;
-
-            if self.player.facing != 0 {
-                attack_sphere_facing = self.player.facing;
-            }
-
+        let input = read_input(ctx, &self.world.movement_config);
+        self.world.update(&input);
 
+        // the world keeps going for a moment so the player sees what happened
+        if self.world.game_over() {
+            self.game_over_for += tick_duration();
 
-            if self.player.facing == 1 {
-                // facing right
-                attack_sphere_velocity = 10.0;
-            } else if self.player.facing == 2 {
-                // facing left
-                attack_sphere_velocity = -10.0;
-            } else if self.player.facing == 3 {
-                // facing up
-                attack_sphere_velocity = -10.0;
-            } else if self.player.facing == 4 {
-                // facing down
-                attack_sphere_velocity = 10.0;
-            } else if self.player.facing == 0 {
-                // go back to the previous facing value because this is the idle pos
-                match self.player.prev_facing {
-                    1 => {
-                        attack_sphere_velocity = 10.0;
-                        attack_sphere_facing = self.player.prev_facing;
-                    },
-                    2 => {
-                        attack_sphere_velocity = -10.0;
-                        attack_sphere_facing = self.player.prev_facing;
-                    },
-                    3 => {
-                        attack_sphere_velocity = -10.0;
-                        attack_sphere_facing = self.player.prev_facing;
-                    },
-                    4 => {
-                        attack_sphere_velocity = 10.0;
-                        attack_sphere_facing = self.player.prev_facing;
-                    },
-                    _ => {
-                        //nothing
-                    },
-                }
+            if self.game_over_for >= GAME_OVER_DELAY {
+                return Ok(Transition::Push(Box::new(GameOverScene)));
             }
-
-            self.player_attack_instances.push(PlayerAttackSphere::new(
-                attack_sphere_animation,
-                attack_sphere_position,
-                attack_sphere_velocity,
-                attack_sphere_facing,
-                attack_sphere_visible,
-            ));
         }
 
-        Ok(())
+        Ok(Transition::None)
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
-
-        graphics::clear(ctx, Color::rgb(0.08, 0.08, 0.08));
+    fn draw(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result {
+        // how far we are between the last update and the next one
+        let alpha = time::get_blend_factor(ctx);
 
-        graphics::set_transform_matrix(ctx, self.camera.as_matrix());
+        let mut view_camera = self.world.camera;
+        view_camera.position = interpolate(self.world.camera_previous_position, self.world.camera.position, alpha);
 
-        let _quarter_second = Duration::from_millis(250);
+        let mut render_camera = Camera::new(view_camera.viewport_width, view_camera.viewport_height);
+        render_camera.position = view_camera.position;
+        render_camera.rotation = view_camera.rotation;
+        render_camera.zoom = view_camera.zoom;
+        render_camera.update();
 
-        let mut player_texture: Texture = Texture::new(ctx, "./resources/sorcerer_walking_down.png")?;
+        graphics::set_transform_matrix(ctx, render_camera.as_matrix());
 
-        if input::is_key_down(ctx, Key::D) {
-            player_texture = Texture::new(ctx, "./resources/sorcerer_walking_right.png")?;
-        } else if input::is_key_down(ctx, Key::A) {
-            player_texture = Texture::new(ctx, "./resources/sorcerer_walking_left.png")?;
-        } else if input::is_key_down(ctx, Key::W) {
-            player_texture = Texture::new(ctx, "./resources/sorcerer_walking_up.png")?;
-        } else if input::is_key_down(ctx, Key::S) {
-            player_texture = Texture::new(ctx, "./resources/sorcerer_walking_down.png")?;
-        } else {
+        self.tilemap.draw(ctx, &self.world.tiles, &self.world.tile_registry, camera_view(&view_camera), self.world.elapsed);
 
-            // 0: sorcerer_idle, facing none
-            // 1: sorcerer_walking_right, facing right
-            // 2: sorcerer_walking_left, facing left
-            // 3: sorcerer_walking_up, facing up
-            // 4: sorcerer_walking_down, facing down
-
-            if self.player.prev_facing == 1 {
-                player_texture = Texture::new(ctx, "./resources/sorcerer_idle_right.png")?;
-            } else if self.player.prev_facing == 2 {
-                player_texture = Texture::new(ctx, "./resources/sorcerer_idle_left.png")?;
-            } else if self.player.prev_facing == 3 {
-                player_texture = Texture::new(ctx, "./resources/sorcerer_idle_up.png")?;
-            } else if self.player.prev_facing == 4 {
-                player_texture = Texture::new(ctx, "./resources/sorcerer_idle_down.png")?;
-            }
+        for (_, sprite, transform) in sprites_to_draw(&self.world) {
+            let position = interpolate(transform.previous_position, transform.position, alpha);
+            draw_sprite(ctx, assets, &self.world.animations, sprite, position)?;
         }
 
-        self.player.animation.set_texture(player_texture);
-        self.player.animation.advance(ctx);
-
-        // This will be inside a loop later
-        // graphics::draw(ctx, &self.attack_ball.animation, self.attack_ball.position);
-
+        // the UI sits still on the screen while the camera moves, shakes and zooms
+        graphics::reset_transform_matrix(ctx);
 
-        for x in &self.tiles {
-            if in_camera_viewport(&self.camera, x) == true {
-                graphics::draw(ctx, &x.texture, x.position);
-            }
-        }
-
-        for x in &mut self.player_attack_instances {
-            graphics::draw(ctx, &x.animation, x.position);
-            x.animation.advance(ctx);
-        }
-
-        if self.player.alive == true {
-            graphics::draw(ctx, &self.npc.animation, self.npc.position);
-            self.npc.animation.advance(ctx);
-
-            graphics::draw(ctx, &self.player.animation, self.player.position);
-        }
+        let world = &self.world;
+        let screen_width = window::get_width(ctx) as f32;
+        let screen_height = window::get_height(ctx) as f32;
 
-        for x in &mut self.enemy_instances {
-            graphics::draw(ctx, &x.animation, x.position);
-            x.animation.advance(ctx);
-        }
-
-        if self.help_menu.visible == true {
-            graphics::draw(ctx, &self.help_menu.texture, self.help_menu.position);
+        if world.help_menu.visible {
+            // in the bottom left corner
+            let help_menu_position = Vec2::new(
+                50.0,
+                screen_height - 20.0 - self.help_menu_texture.height() as f32,
+            );
+            graphics::draw(ctx, &self.help_menu_texture, help_menu_position);
 
-            let help_menu_font = Font::from_file_data(ctx, include_bytes!("../resources/prstart.ttf"));
-            let help_menu_text = Text::new(&self.help_menu.text, help_menu_font, 16.0);
+            let help_menu_font = assets.font(ctx, "./resources/prstart.ttf")?;
+            let help_menu_text = Text::new(&world.help_menu.text, help_menu_font, 16.0);
             let help_menu_text_position = Vec2::new(
-                self.help_menu.position.x + 25.0,
-                self.help_menu.position.y + 25.0,
+                help_menu_position.x + 25.0,
+                help_menu_position.y + 25.0,
             );
             graphics::draw(ctx, &help_menu_text, help_menu_text_position);
         }
 
-        if self.dialogue_box.visible == true {
-            let string_char_vec: Vec<char> = self.dialogue_box.text.chars().collect();
-            let dialogue_box_font = Font::from_file_data(ctx, include_bytes!("../resources/prstart.ttf"));
-            let dialogue_box_text = Text::new(&self.dialogue_box.output_text, dialogue_box_font, 12.0);
+        // the text is wrapped and typed out in `World::update`, this only draws it
+        if world.dialogue_box.visible {
+            // centered at the bottom
+            let dialogue_box_position = Vec2::new(
+                ((screen_width - self.dialogue_box_texture.width() as f32) / 2.0).round(),
+                screen_height - 20.0 - self.dialogue_box_texture.height() as f32,
+            );
+            let dialogue_box_font = assets.font(ctx, "./resources/prstart.ttf")?;
+            let dialogue_box_text = Text::new(&world.dialogue_box.output_text, dialogue_box_font, 12.0);
             let dialogue_box_text_position = Vec2::new(
-                self.dialogue_box.position.x + 15.0,
-                self.dialogue_box.position.y + 25.0,
+                dialogue_box_position.x + 15.0,
+                dialogue_box_position.y + 25.0,
             );
 
-            let mut current_line: String = String::from(":");
-
-            if self.dialogue_box.text_wrap_finished == false {
-                for c in &string_char_vec {
-                    self.dialogue_box.output_text.push_str(&c.to_string());
-                    current_line.push_str(&c.to_string());
-                    let test_text = Text::new(&current_line, dialogue_box_font, 12.0);
-                    // graphics::draw(ctx, &test_text, dialogue_box_text_position);
-
-                    if test_text.get_bounds(ctx).unwrap().width > 840.0 {
-                        let new_line: &str = "\n";
-                        self.dialogue_box.output_text.push_str(new_line);
-                        current_line = String::from(":");
-                    }
-
-                    let index = string_char_vec.iter().position(|r| r == c).unwrap();
-
-                    if index == string_char_vec.len() - 1 {
-                        self.dialogue_box.text_wrap_finished = true;
-                    }
+            graphics::draw(ctx, &self.dialogue_box_texture, dialogue_box_position);
+            graphics::draw(ctx, &dialogue_box_text, dialogue_box_text_position);
 
-                    println!("{}", self.dialogue_box.text_wrap_finished);
-                }
+            // the answers go under the text, the highlighted one marked with `>`
+            if world.dialogue_box.choices_shown() {
+                let choices: Vec<String> = world.dialogue_box.choices.iter().enumerate()
+                    .map(|(index, choice)| format!("{} {}", if index == world.dialogue_box.selected { ">" } else { " " }, choice))
+                    .collect();
+                let choices_text = Text::new(choices.join("\n"), dialogue_box_font, 12.0);
+                let text_height = dialogue_box_text.get_bounds(ctx).map_or(0.0, |bounds| bounds.height);
+                let choices_position = Vec2::new(
+                    dialogue_box_text_position.x,
+                    dialogue_box_text_position.y + text_height + 16.0,
+                );
+                graphics::draw(ctx, &choices_text, choices_position);
             }
 
-            graphics::draw(ctx, &self.dialogue_box.texture, self.dialogue_box.position);
-            graphics::draw(ctx, &dialogue_box_text, dialogue_box_text_position);
+            // a blinking arrow in the corner while there is more to read
+            if world.dialogue_box.more() && (world.elapsed.as_millis() / 400).is_multiple_of(2) {
+                let more_text = Text::new("v", dialogue_box_font, 12.0);
+                let more_position = Vec2::new(
+                    dialogue_box_position.x + self.dialogue_box_texture.width() as f32 - 30.0,
+                    dialogue_box_position.y + self.dialogue_box_texture.height() as f32 - 30.0,
+                );
+                graphics::draw(ctx, &more_text, more_position);
+            }
         }
 
         Ok(())
     }
+
+    // nobody is playing while the window is in the background
+    fn event(&mut self, _ctx: &mut Context, event: &Event) -> Transition {
+        match event {
+            Event::FocusLost => Transition::Push(Box::new(PauseScene)),
+            _ => Transition::None,
+        }
+    }
 }
 
 fn main() -> tetra::Result {
     ContextBuilder::new("HYPOTERRA", WINDOW_WIDTH as i32, WINDOW_HEIGHT as i32)
-        .quit_on_escape(true)
+        .timestep(Timestep::Fixed(TICKS_PER_SECOND))
         .build()?
-        .run(GameState::new)
+        .run(|_| Ok(SceneStack::new(Box::new(TitleScene), Assets::new())))
 }
@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::util::load_json;
+
+// Speeds are in pixels per second, acceleration and deceleration in pixels per second
+// per second.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct MovementConfig {
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    // stick deflection below this is ignored, so a worn stick doesn't make the player drift
+    pub gamepad_dead_zone: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> MovementConfig {
+        MovementConfig {
+            max_speed: 360.0,
+            acceleration: 5400.0,
+            deceleration: 7200.0,
+            gamepad_dead_zone: 0.2,
+        }
+    }
+}
+
+impl MovementConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<MovementConfig> {
+        load_json(path)
+    }
+}
+
+// Radial dead zone, rescaled so movement starts at 0 right outside the dead zone.
+pub fn apply_dead_zone(stick: Vec2<f32>, dead_zone: f32) -> Vec2<f32> {
+    let length = stick.magnitude();
+
+    if length <= dead_zone {
+        return Vec2::zero();
+    }
+
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+
+    stick / length * scaled
+}
+
+// Accelerates `velocity` towards `input * max_speed` over `dt` seconds, or slows it down
+// to a stop when there is no input. `friction` is that of the ground and scales both rates.
+pub fn step_velocity(velocity: Vec2<f32>, input: Vec2<f32>, config: &MovementConfig, friction: f32, dt: f32) -> Vec2<f32> {
+    let target = input * config.max_speed;
+    let rate = dt * friction * if input == Vec2::zero() {
+        config.deceleration
+    } else {
+        config.acceleration
+    };
+
+    let difference = target - velocity;
+    let distance = difference.magnitude();
+
+    if distance <= rate {
+        target
+    } else {
+        velocity + difference / distance * rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn dead_zone_ignores_small_deflection() {
+        assert_eq!(apply_dead_zone(Vec2::new(0.1, -0.1), 0.2), Vec2::zero());
+        assert_eq!(apply_dead_zone(Vec2::new(0.0, 0.2), 0.2), Vec2::zero());
+        assert_eq!(apply_dead_zone(Vec2::zero(), 0.2), Vec2::zero());
+    }
+
+    #[test]
+    fn dead_zone_rescales_what_is_left() {
+        // just outside the dead zone movement starts from nothing
+        let barely = apply_dead_zone(Vec2::new(0.21, 0.0), 0.2);
+        assert!(barely.x > 0.0 && barely.x < 0.02);
+
+        // half way between the dead zone and the edge is half speed, in the same direction
+        let half = apply_dead_zone(Vec2::new(0.0, -0.6), 0.2);
+        assert!(close(half.y, -0.5) && half.x == 0.0);
+
+        // and full tilt is full speed, also when a square stick reports more than 1
+        assert!(close(apply_dead_zone(Vec2::new(1.0, 0.0), 0.2).magnitude(), 1.0));
+        assert!(close(apply_dead_zone(Vec2::new(1.0, 1.0), 0.2).magnitude(), 1.0));
+    }
+
+    #[test]
+    fn acceleration_and_deceleration_are_limited() {
+        let config = MovementConfig::default();
+        let right = Vec2::new(1.0, 0.0);
+
+        // 5400 px/s² for a tick is 90 px/s faster
+        let velocity = step_velocity(Vec2::zero(), right, &config, 1.0, DT);
+        assert!(close(velocity.x, config.acceleration * DT));
+
+        // up to the maximum and no further
+        let mut velocity = Vec2::zero();
+        for _ in 0..10 {
+            velocity = step_velocity(velocity, right, &config, 1.0, DT);
+        }
+        assert_eq!(velocity, Vec2::new(config.max_speed, 0.0));
+
+        // letting go slows down at 7200 px/s²
+        let slowed = step_velocity(velocity, Vec2::zero(), &config, 1.0, DT);
+        assert!(close(slowed.x, config.max_speed - config.deceleration * DT));
+
+        // and stops dead instead of going backwards
+        assert_eq!(step_velocity(Vec2::new(50.0, 0.0), Vec2::zero(), &config, 1.0, DT), Vec2::zero());
+
+        // friction scales both
+        let slippery = step_velocity(Vec2::zero(), right, &config, 0.5, DT);
+        assert!(close(slippery.x, config.acceleration * DT * 0.5));
+    }
+
+    #[test]
+    fn diagonals_are_not_faster() {
+        let config = MovementConfig::default();
+        let diagonal = Vec2::new(1.0, 1.0).normalized();
+        let mut velocity = Vec2::zero();
+
+        for _ in 0..30 {
+            velocity = step_velocity(velocity, diagonal, &config, 1.0, DT);
+            assert!(velocity.magnitude() <= config.max_speed + 1e-3);
+        }
+
+        assert!(close(velocity.magnitude(), config.max_speed));
+        assert!(close(velocity.x, velocity.y));
+
+        // nor is turning from one diagonal to the other
+        let turned = Vec2::new(-1.0, 1.0).normalized();
+        for _ in 0..30 {
+            velocity = step_velocity(velocity, turned, &config, 1.0, DT);
+            assert!(velocity.magnitude() <= config.max_speed + 1e-3);
+        }
+    }
+}
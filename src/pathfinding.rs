@@ -0,0 +1,499 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use vek::Vec2;
+
+use crate::level::TILE_SIZE;
+use crate::physics::CollisionGrid;
+
+// A cell of the tile grid, (column, row).
+pub type Cell = (i32, i32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Connectivity {
+    // up, down, left and right only
+    Four,
+    // diagonals too
+    Eight,
+}
+
+// When a diagonal step past a solid cell is allowed. With `Never` an agent never clips the
+// corner of a wall, with `OneSideFree` it can't squeeze between two walls that touch at
+// their corners and with `Always` only the cell it steps into has to be free.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CornerCutting {
+    Always,
+    OneSideFree,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PathSettings {
+    pub connectivity: Connectivity,
+    pub corners: CornerCutting,
+    // gives up after looking at this many cells, so an unreachable goal on a big map
+    // doesn't stall a tick
+    pub max_nodes: usize,
+}
+
+impl Default for PathSettings {
+    fn default() -> PathSettings {
+        PathSettings {
+            connectivity: Connectivity::Eight,
+            corners: CornerCutting::Never,
+            max_nodes: 4096,
+        }
+    }
+}
+
+// step costs, diagonals are roughly sqrt(2) times as long
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+
+const STRAIGHTS: [Cell; 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const DIAGONALS: [Cell; 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+// Which cells an agent can stand in, with its top left corner at the top left of the
+// cell. An agent `clearance` cells wide needs that many free cells to the right of and
+// below its cell. Everything outside the map is blocked.
+pub struct NavGrid {
+    width: i32,
+    height: i32,
+    walkable: Vec<bool>,
+}
+
+// An open cell in the A* queue. BinaryHeap is a max-heap, so the ordering is reversed to
+// pop the lowest estimate first, and of those the one furthest along.
+#[derive(PartialEq, Eq)]
+struct Open {
+    estimate: u32,
+    cost: u32,
+    index: usize,
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Open) -> Ordering {
+        other.estimate.cmp(&self.estimate).then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl NavGrid {
+    pub fn new<F>(width: i32, height: i32, clearance: i32, is_solid: F) -> NavGrid
+    where
+        F: Fn(i32, i32) -> bool,
+    {
+        let clearance = clearance.max(1);
+        let mut walkable = vec![false; (width.max(0) * height.max(0)) as usize];
+
+        for y in 0..height {
+            for x in 0..width {
+                let fits = x + clearance <= width &&
+                    y + clearance <= height &&
+                    (y..y + clearance).all(|y| (x..x + clearance).all(|x| !is_solid(x, y)));
+
+                walkable[(y * width + x) as usize] = fits;
+            }
+        }
+
+        NavGrid { width, height, walkable }
+    }
+
+    pub fn from_collision_grid(grid: &CollisionGrid, clearance: i32) -> NavGrid {
+        NavGrid::new(grid.width(), grid.height(), clearance, |x, y| grid.is_solid(x, y))
+    }
+
+    pub fn is_walkable(&self, (x, y): Cell) -> bool {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return false;
+        }
+
+        self.walkable[(y * self.width + x) as usize]
+    }
+
+    fn index(&self, (x, y): Cell) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn cell(&self, index: usize) -> Cell {
+        (index as i32 % self.width, index as i32 / self.width)
+    }
+
+    // The walkable cells one step from `cell`, with what the step costs.
+    pub fn neighbours(&self, (x, y): Cell, settings: &PathSettings) -> Vec<(Cell, u32)> {
+        let mut neighbours = Vec::with_capacity(8);
+
+        for &(dx, dy) in STRAIGHTS.iter() {
+            if self.is_walkable((x + dx, y + dy)) {
+                neighbours.push(((x + dx, y + dy), STRAIGHT));
+            }
+        }
+
+        if settings.connectivity == Connectivity::Four {
+            return neighbours;
+        }
+
+        for &(dx, dy) in DIAGONALS.iter() {
+            if !self.is_walkable((x + dx, y + dy)) {
+                continue;
+            }
+
+            let side_x = self.is_walkable((x + dx, y));
+            let side_y = self.is_walkable((x, y + dy));
+            let allowed = match settings.corners {
+                CornerCutting::Always => true,
+                CornerCutting::OneSideFree => side_x || side_y,
+                CornerCutting::Never => side_x && side_y,
+            };
+
+            if allowed {
+                neighbours.push(((x + dx, y + dy), DIAGONAL));
+            }
+        }
+
+        neighbours
+    }
+
+    // The closest walkable cell to `cell` within `radius` cells, `cell` itself if it is
+    // walkable.
+    pub fn nearest_walkable(&self, cell: Cell, radius: i32) -> Option<Cell> {
+        (0..=radius).find_map(|ring| {
+            let mut candidates: Vec<Cell> = (-ring..=ring)
+                .flat_map(|dy| (-ring..=ring).map(move |dx| (cell.0 + dx, cell.1 + dy)))
+                .filter(|&(x, y)| (x - cell.0).abs() == ring || (y - cell.1).abs() == ring)
+                .filter(|&candidate| self.is_walkable(candidate))
+                .collect();
+
+            candidates.sort_by_key(|&(x, y)| (x - cell.0).pow(2) + (y - cell.1).pow(2));
+            candidates.first().copied()
+        })
+    }
+
+    // The cheapest path from `start` to `goal`, both included, or None if there isn't
+    // one or it takes looking at more than `settings.max_nodes` cells to find out.
+    pub fn find_path(&self, start: Cell, goal: Cell, settings: &PathSettings) -> Option<Vec<Cell>> {
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+
+        let goal_index = self.index(goal);
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<usize, u32> = HashMap::new();
+        let mut came_from: HashMap<usize, usize> = HashMap::new();
+        let mut expanded = 0;
+
+        costs.insert(self.index(start), 0);
+        open.push(Open {
+            estimate: heuristic(start, goal, settings.connectivity),
+            cost: 0,
+            index: self.index(start),
+        });
+
+        while let Some(Open { cost, index, .. }) = open.pop() {
+            if index == goal_index {
+                let mut path = vec![goal];
+                let mut current = index;
+
+                while let Some(&previous) = came_from.get(&current) {
+                    path.push(self.cell(previous));
+                    current = previous;
+                }
+
+                path.reverse();
+                return Some(path);
+            }
+
+            // a cheaper way here was found after this entry was queued
+            if costs.get(&index).is_some_and(|&best| cost > best) {
+                continue;
+            }
+
+            expanded += 1;
+            if expanded > settings.max_nodes {
+                return None;
+            }
+
+            for (next, step) in self.neighbours(self.cell(index), settings) {
+                let next_index = self.index(next);
+                let next_cost = cost + step;
+
+                if costs.get(&next_index).is_none_or(|&best| next_cost < best) {
+                    costs.insert(next_index, next_cost);
+                    came_from.insert(next_index, index);
+                    open.push(Open {
+                        estimate: next_cost + heuristic(next, goal, settings.connectivity),
+                        cost: next_cost,
+                        index: next_index,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// never overestimates, so A* still finds the cheapest path
+fn heuristic(from: Cell, to: Cell, connectivity: Connectivity) -> u32 {
+    let dx = (from.0 - to.0).unsigned_abs();
+    let dy = (from.1 - to.1).unsigned_abs();
+
+    match connectivity {
+        Connectivity::Four => STRAIGHT * (dx + dy),
+        Connectivity::Eight => STRAIGHT * dx.max(dy) + (DIAGONAL - STRAIGHT) * dx.min(dy),
+    }
+}
+
+// cached paths are thrown away all at once when there are this many
+const CACHE_SIZE: usize = 512;
+
+// how far to look for a free cell when a start or goal is up against a wall
+const NEAREST_RADIUS: i32 = 2;
+
+// Finds paths through a level for agents of any size, in world pixels. Paths are cached by
+// start and goal cell, so asking again every tick only searches when either moves to
+// another cell.
+pub struct Pathfinder {
+    width: i32,
+    height: i32,
+    solid: Vec<bool>,
+    // one per agent size in cells, built when first needed
+    grids: HashMap<i32, NavGrid>,
+    cache: HashMap<(i32, Cell, Cell), Option<Vec<Cell>>>,
+    pub settings: PathSettings,
+}
+
+impl Pathfinder {
+    pub fn new(grid: &CollisionGrid, settings: PathSettings) -> Pathfinder {
+        let (width, height) = (grid.width(), grid.height());
+        let solid = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| grid.is_solid(x, y)).collect();
+
+        Pathfinder {
+            width,
+            height,
+            solid,
+            grids: HashMap::new(),
+            cache: HashMap::new(),
+            settings,
+        }
+    }
+
+    // how many cells across an agent of `size` pixels needs
+    pub fn clearance(size: Vec2<f32>) -> i32 {
+        ((size.x.max(size.y) / TILE_SIZE).ceil() as i32).max(1)
+    }
+
+    // the cell whose top left corner is nearest to `position`
+    pub fn cell_at(position: Vec2<f32>) -> Cell {
+        ((position.x / TILE_SIZE).round() as i32, (position.y / TILE_SIZE).round() as i32)
+    }
+
+    pub fn cell_position((x, y): Cell) -> Vec2<f32> {
+        Vec2::new(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE)
+    }
+
+    pub fn grid(&mut self, clearance: i32) -> &NavGrid {
+        let (width, height, solid) = (self.width, self.height, &self.solid);
+
+        self.grids.entry(clearance).or_insert_with(|| {
+            NavGrid::new(width, height, clearance, |x, y| solid[(y * width + x) as usize])
+        })
+    }
+
+    // A path of cells for an agent `clearance` cells across, cached.
+    pub fn find_cells(&mut self, start: Cell, goal: Cell, clearance: i32) -> Option<Vec<Cell>> {
+        let key = (clearance, start, goal);
+
+        if let Some(path) = self.cache.get(&key) {
+            return path.clone();
+        }
+
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.clear();
+        }
+
+        let settings = self.settings;
+        let path = self.grid(clearance).find_path(start, goal, &settings);
+        self.cache.insert(key, path.clone());
+
+        path
+    }
+
+    // The way from `from` to `to` for an agent of `size`, all as top left corners. Every
+    // waypoint but the last is the corner of a cell along the path, the last one is `to`
+    // unless `to` is too close to a wall for the agent, then it is the nearest cell that
+    // isn't.
+    pub fn find_path(&mut self, from: Vec2<f32>, to: Vec2<f32>, size: Vec2<f32>) -> Option<Vec<Vec2<f32>>> {
+        let clearance = Pathfinder::clearance(size);
+        let grid = self.grid(clearance);
+
+        let floor = ((from.x / TILE_SIZE).floor() as i32, (from.y / TILE_SIZE).floor() as i32);
+        let start = [Pathfinder::cell_at(from), floor]
+            .iter()
+            .copied()
+            .find(|&cell| grid.is_walkable(cell))
+            .or_else(|| grid.nearest_walkable(floor, NEAREST_RADIUS))?;
+
+        let wanted = Pathfinder::cell_at(to);
+        let goal_reachable = grid.is_walkable(wanted);
+        let goal = grid.nearest_walkable(wanted, NEAREST_RADIUS)?;
+
+        let cells = self.find_cells(start, goal, clearance)?;
+        let mut waypoints: Vec<Vec2<f32>> = cells.into_iter().skip(1).map(Pathfinder::cell_position).collect();
+
+        if goal_reachable {
+            waypoints.pop();
+            waypoints.push(to);
+        }
+
+        Some(waypoints)
+    }
+
+    // Where an agent at `from` should head next on its way to `to`, None if it can't get
+    // there.
+    pub fn next_waypoint(&mut self, from: Vec2<f32>, to: Vec2<f32>, size: Vec2<f32>) -> Option<Vec2<f32>> {
+        self.find_path(from, to, size).map(|path| path.first().copied().unwrap_or(to))
+    }
+
+    // for when the level's collision changes, forgets every grid and path
+    pub fn rebuild(&mut self, grid: &CollisionGrid) {
+        *self = Pathfinder::new(grid, self.settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nav_grid(rows: &[&str]) -> NavGrid {
+        NavGrid::from_collision_grid(&CollisionGrid::from_rows(rows), 1)
+    }
+
+    fn settings(connectivity: Connectivity, corners: CornerCutting) -> PathSettings {
+        PathSettings {
+            connectivity,
+            corners,
+            ..PathSettings::default()
+        }
+    }
+
+    #[test]
+    fn finds_a_straight_path() {
+        let grid = nav_grid(&["....."]);
+        let path = grid.find_path((0, 0), (4, 0), &PathSettings::default());
+
+        assert_eq!(path, Some(vec![(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]));
+    }
+
+    #[test]
+    fn goes_around_a_wall() {
+        let grid = nav_grid(&[
+            ".....",
+            "..#..",
+            "..#..",
+            ".....",
+        ]);
+        let path = grid.find_path((0, 1), (4, 1), &settings(Connectivity::Four, CornerCutting::Never)).unwrap();
+
+        // over the top of the wall, the shorter way round, one step at a time
+        assert_eq!(path.len(), 7);
+        assert_eq!((path[0], path[6]), ((0, 1), (4, 1)));
+        assert!(path.contains(&(2, 0)));
+        assert!(path.iter().all(|&cell| grid.is_walkable(cell)));
+        assert!(path.windows(2).all(|step| (step[0].0 - step[1].0).abs() + (step[0].1 - step[1].1).abs() == 1));
+    }
+
+    #[test]
+    fn no_path_to_a_boxed_in_goal() {
+        let grid = nav_grid(&[
+            ".....",
+            ".###.",
+            ".#.#.",
+            ".###.",
+        ]);
+
+        assert_eq!(grid.find_path((0, 0), (2, 2), &PathSettings::default()), None);
+        // nor from inside it
+        assert_eq!(grid.find_path((2, 2), (0, 0), &PathSettings::default()), None);
+    }
+
+    #[test]
+    fn start_and_goal_in_the_same_cell() {
+        let grid = nav_grid(&["..."]);
+
+        assert_eq!(grid.find_path((1, 0), (1, 0), &PathSettings::default()), Some(vec![(1, 0)]));
+    }
+
+    #[test]
+    fn corner_cutting_decides_diagonal_steps() {
+        // two walls touching at their corners
+        let squeeze = nav_grid(&[
+            ".#",
+            "#.",
+        ]);
+        assert_eq!(squeeze.find_path((0, 0), (1, 1), &settings(Connectivity::Eight, CornerCutting::Never)), None);
+        assert_eq!(squeeze.find_path((0, 0), (1, 1), &settings(Connectivity::Eight, CornerCutting::OneSideFree)), None);
+        assert_eq!(
+            squeeze.find_path((0, 0), (1, 1), &settings(Connectivity::Eight, CornerCutting::Always)),
+            Some(vec![(0, 0), (1, 1)])
+        );
+
+        // one wall beside the diagonal
+        let corner = nav_grid(&[
+            "..",
+            "#.",
+        ]);
+        assert_eq!(
+            corner.find_path((0, 0), (1, 1), &settings(Connectivity::Eight, CornerCutting::Never)),
+            Some(vec![(0, 0), (1, 0), (1, 1)])
+        );
+        assert_eq!(
+            corner.find_path((0, 0), (1, 1), &settings(Connectivity::Eight, CornerCutting::OneSideFree)),
+            Some(vec![(0, 0), (1, 1)])
+        );
+    }
+
+    #[test]
+    fn next_waypoint_is_the_first_cell_along_the_way() {
+        let grid = CollisionGrid::from_rows(&[
+            ".....",
+            "..#..",
+        ]);
+        let mut pathfinder = Pathfinder::new(&grid, PathSettings::default());
+        let size = Vec2::new(16.0, 16.0);
+
+        let to = Vec2::new(4.0 * TILE_SIZE, 0.0);
+        assert_eq!(pathfinder.next_waypoint(Vec2::zero(), to, size), Some(Vec2::new(TILE_SIZE, 0.0)));
+
+        // already there
+        assert_eq!(pathfinder.next_waypoint(to, to, size), Some(to));
+    }
+
+    #[test]
+    fn paths_are_cached_until_the_cache_is_full() {
+        let rows = vec!["................................"; 32];
+        let grid = CollisionGrid::from_rows(&rows);
+        let mut pathfinder = Pathfinder::new(&grid, PathSettings::default());
+
+        let first = pathfinder.find_cells((0, 0), (5, 3), 1);
+        assert!(first.is_some());
+        assert_eq!(pathfinder.find_cells((0, 0), (5, 3), 1), first);
+        assert_eq!(pathfinder.cache.len(), 1);
+
+        // fill it up with other start and goal pairs
+        for goal in (0..32).flat_map(|y| (0..32).map(move |x| (x, y))).filter(|&goal| goal != (5, 3)).take(CACHE_SIZE - 1) {
+            pathfinder.find_cells((0, 0), goal, 1);
+        }
+        assert_eq!(pathfinder.cache.len(), CACHE_SIZE);
+        assert_eq!(pathfinder.find_cells((0, 0), (5, 3), 1), first);
+
+        // the next new path throws every cached one away first
+        pathfinder.find_cells((1, 1), (2, 2), 1);
+        assert_eq!(pathfinder.cache.len(), 1);
+    }
+}
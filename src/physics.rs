@@ -0,0 +1,383 @@
+use vek::Vec2;
+
+use crate::game_structs::Tile;
+use crate::level::TILE_SIZE;
+
+// Which cells of the map hold a collidable tile, and what the tiles in each cell do to
+// whatever stands on them. Tiles sit on a TILE_SIZE grid, so an AABB only has to be tested
+// against the handful of cells it overlaps instead of every tile in the level.
+pub struct CollisionGrid {
+    width: i32,
+    height: i32,
+    solid: Vec<bool>,
+    damage: Vec<i32>,
+    friction: Vec<f32>,
+}
+
+impl CollisionGrid {
+    pub fn from_tiles(tiles: &[Tile], width: usize, height: usize) -> CollisionGrid {
+        let mut solid = vec![false; width * height];
+        let mut damage = vec![0; width * height];
+        let mut friction = vec![1.0; width * height];
+
+        // tiles come sorted by layer, so the friction of a cell is that of its top tile,
+        // while a damaging tile hurts even under a decoration
+        for tile in tiles {
+            let x = (tile.position.x / TILE_SIZE) as usize;
+            let y = (tile.position.y / TILE_SIZE) as usize;
+
+            if x < width && y < height {
+                let cell = y * width + x;
+
+                solid[cell] |= tile.collidable;
+                damage[cell] = damage[cell].max(tile.damage);
+                friction[cell] = tile.friction;
+            }
+        }
+
+        CollisionGrid {
+            width: width as i32,
+            height: height as i32,
+            solid,
+            damage,
+            friction,
+        }
+    }
+
+    // in cells
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // cells outside the map are empty, like they were before the grid existed
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.cell(x, y).is_some_and(|cell| self.solid[cell])
+    }
+
+    // The cells an AABB overlaps. Edges are exclusive, matching `util::collision`, so a
+    // box that only touches a cell's edge is not inside it.
+    pub fn cells_overlapping(position: Vec2<f32>, width: f32, height: f32) -> impl Iterator<Item = (i32, i32)> {
+        let first_x = (position.x / TILE_SIZE).floor() as i32;
+        let first_y = (position.y / TILE_SIZE).floor() as i32;
+        let last_x = ((position.x + width) / TILE_SIZE).ceil() as i32 - 1;
+        let last_y = ((position.y + height) / TILE_SIZE).ceil() as i32 - 1;
+
+        (first_y..=last_y).flat_map(move |y| (first_x..=last_x).map(move |x| (x, y)))
+    }
+
+    pub fn solid_cells_overlapping(&self, position: Vec2<f32>, width: f32, height: f32) -> impl Iterator<Item = (i32, i32)> + '_ {
+        CollisionGrid::cells_overlapping(position, width, height).filter(move |&(x, y)| self.is_solid(x, y))
+    }
+
+    pub fn overlaps_solid(&self, position: Vec2<f32>, width: f32, height: f32) -> bool {
+        self.solid_cells_overlapping(position, width, height).next().is_some()
+    }
+
+    fn cell(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some((y * self.width + x) as usize)
+    }
+
+    // the most damage any cell an AABB overlaps deals, 0 off the map
+    pub fn damage_overlapping(&self, position: Vec2<f32>, width: f32, height: f32) -> i32 {
+        CollisionGrid::cells_overlapping(position, width, height)
+            .filter_map(|(x, y)| self.cell(x, y))
+            .map(|cell| self.damage[cell])
+            .max()
+            .unwrap_or(0)
+    }
+
+    // the friction of the cell under a point, 1 off the map
+    pub fn friction_at(&self, point: Vec2<f32>) -> f32 {
+        let x = (point.x / TILE_SIZE).floor() as i32;
+        let y = (point.y / TILE_SIZE).floor() as i32;
+
+        self.cell(x, y).map_or(1.0, |cell| self.friction[cell])
+    }
+}
+
+pub struct MoveResult {
+    pub position: Vec2<f32>,
+    pub blocked_x: bool,
+    pub blocked_y: bool,
+}
+
+// Moves an AABB by `motion`, first along x and then along y, stopping flush against the
+// first collidable cell in the way. Whatever motion isn't blocked is kept, so a box moving
+// diagonally into a wall slides along it. Boxes that start inside a wall are pushed out
+// first, so the result never overlaps a collidable cell.
+pub fn move_and_slide(grid: &CollisionGrid, position: Vec2<f32>, size: Vec2<f32>, motion: Vec2<f32>) -> MoveResult {
+    let mut position = depenetrate(grid, position, size);
+
+    let (x, blocked_x) = sweep_axis(position.x, position.y, size.x, size.y, motion.x, |column, row| {
+        grid.is_solid(column, row)
+    });
+    position.x = x;
+
+    // the same sweep with the axes swapped
+    let (y, blocked_y) = sweep_axis(position.y, position.x, size.y, size.x, motion.y, |row, column| {
+        grid.is_solid(column, row)
+    });
+    position.y = y;
+
+    MoveResult {
+        position,
+        blocked_x,
+        blocked_y,
+    }
+}
+
+// Sweeps along one axis. `start`/`length` are the box along the moving axis and
+// `cross_start`/`cross_length` along the other one; `solid` takes (moving, cross) cells.
+fn sweep_axis<F>(
+    start: f32,
+    cross_start: f32,
+    length: f32,
+    cross_length: f32,
+    delta: f32,
+    solid: F,
+) -> (f32, bool)
+where
+    F: Fn(i32, i32) -> bool,
+{
+    if delta == 0.0 {
+        return (start, false);
+    }
+
+    let first_cross = (cross_start / TILE_SIZE).floor() as i32;
+    let last_cross = ((cross_start + cross_length) / TILE_SIZE).ceil() as i32 - 1;
+    let blocked = |cell: i32| (first_cross..=last_cross).any(|cross| solid(cell, cross));
+
+    if delta > 0.0 {
+        let front = start + length;
+        let first_cell = (front / TILE_SIZE).floor() as i32;
+        let last_cell = ((front + delta) / TILE_SIZE).ceil() as i32 - 1;
+
+        for cell in first_cell..=last_cell {
+            if blocked(cell) {
+                return ((cell as f32 * TILE_SIZE - length).max(start), true);
+            }
+        }
+    } else {
+        let first_cell = (start / TILE_SIZE).ceil() as i32 - 1;
+        let last_cell = ((start + delta) / TILE_SIZE).floor() as i32;
+
+        for cell in (last_cell..=first_cell).rev() {
+            if blocked(cell) {
+                return (((cell + 1) as f32 * TILE_SIZE).min(start), true);
+            }
+        }
+    }
+
+    (start + delta, false)
+}
+
+// Pushes a box out of any collidable cells it overlaps, along whichever axis needs the
+// smallest correction.
+pub fn depenetrate(grid: &CollisionGrid, position: Vec2<f32>, size: Vec2<f32>) -> Vec2<f32> {
+    let mut position = position;
+
+    // a box wedged between walls on both sides may need a few passes
+    for _ in 0..8 {
+        let (x, y) = match grid.solid_cells_overlapping(position, size.x, size.y).next() {
+            Some(cell) => cell,
+            None => break,
+        };
+
+        let cell_left = x as f32 * TILE_SIZE;
+        let cell_top = y as f32 * TILE_SIZE;
+
+        let push_left = position.x + size.x - cell_left;
+        let push_right = cell_left + TILE_SIZE - position.x;
+        let push_up = position.y + size.y - cell_top;
+        let push_down = cell_top + TILE_SIZE - position.y;
+
+        let mut candidates = [
+            Vec2::new(position.x - push_left, position.y),
+            Vec2::new(position.x + push_right, position.y),
+            Vec2::new(position.x, position.y - push_up),
+            Vec2::new(position.x, position.y + push_down),
+        ];
+        candidates.sort_by(|a, b| {
+            let distance_a = (a.x - position.x).abs() + (a.y - position.y).abs();
+            let distance_b = (b.x - position.x).abs() + (b.y - position.y).abs();
+            distance_a.total_cmp(&distance_b)
+        });
+
+        // prefer the smallest push that ends up somewhere free, otherwise take the
+        // smallest one and deal with the next cell on the following pass
+        position = candidates
+            .iter()
+            .copied()
+            .find(|candidate| !grid.overlaps_solid(*candidate, size.x, size.y))
+            .unwrap_or(candidates[0]);
+    }
+
+    position
+}
+
+#[cfg(test)]
+impl CollisionGrid {
+    // A grid drawn as text, one string per row: `#` is a collidable cell, anything else
+    // is empty.
+    pub(crate) fn from_rows(rows: &[&str]) -> CollisionGrid {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let solid: Vec<bool> = rows
+            .iter()
+            .flat_map(|row| (0..width).map(move |x| row.as_bytes().get(x) == Some(&b'#')))
+            .collect();
+
+        CollisionGrid {
+            width: width as i32,
+            height: rows.len() as i32,
+            damage: vec![0; solid.len()],
+            friction: vec![1.0; solid.len()],
+            solid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(position: Vec2<f32>, width: f32, height: f32) -> Vec<(i32, i32)> {
+        CollisionGrid::cells_overlapping(position, width, height).collect()
+    }
+
+    #[test]
+    fn touching_an_edge_is_not_overlapping() {
+        let grid = CollisionGrid::from_rows(&[
+            ".#.",
+            "...",
+            ".#.",
+        ]);
+
+        // flush against the left edge of the top wall, then between the two walls
+        assert_eq!(cells(Vec2::new(0.0, 0.0), TILE_SIZE, TILE_SIZE), vec![(0, 0)]);
+        assert!(!grid.overlaps_solid(Vec2::new(0.0, 0.0), TILE_SIZE, TILE_SIZE));
+        assert!(!grid.overlaps_solid(Vec2::new(TILE_SIZE, TILE_SIZE), TILE_SIZE, TILE_SIZE));
+        assert!(!grid.overlaps_solid(Vec2::new(TILE_SIZE * 2.0, 0.0), TILE_SIZE, TILE_SIZE));
+    }
+
+    #[test]
+    fn one_pixel_into_a_corner_overlaps() {
+        let grid = CollisionGrid::from_rows(&[
+            "...",
+            ".#.",
+        ]);
+        let position = Vec2::new(1.0, 1.0);
+
+        assert_eq!(cells(position, TILE_SIZE, TILE_SIZE), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(grid.solid_cells_overlapping(position, TILE_SIZE, TILE_SIZE).collect::<Vec<_>>(), vec![(1, 1)]);
+        assert!(grid.overlaps_solid(position, TILE_SIZE, TILE_SIZE));
+        assert!(!grid.overlaps_solid(Vec2::zero(), TILE_SIZE, TILE_SIZE));
+    }
+
+    #[test]
+    fn negative_coordinates_round_down() {
+        let grid = CollisionGrid::from_rows(&[
+            "#.",
+            "..",
+        ]);
+
+        assert_eq!(cells(Vec2::new(-10.0, -10.0), 20.0, 20.0), vec![(-1, -1), (0, -1), (-1, 0), (0, 0)]);
+        assert!(grid.overlaps_solid(Vec2::new(-10.0, -10.0), 20.0, 20.0));
+
+        // above and left of the map is empty
+        assert_eq!(cells(Vec2::new(-30.0, -30.0), 20.0, 20.0), vec![(-1, -1)]);
+        assert!(!grid.overlaps_solid(Vec2::new(-30.0, -30.0), 20.0, 20.0));
+    }
+
+    #[test]
+    fn past_the_far_edge_is_empty() {
+        let grid = CollisionGrid::from_rows(&[
+            "..#",
+            "..#",
+        ]);
+        let right = TILE_SIZE * 3.0;
+        let bottom = TILE_SIZE * 2.0;
+
+        // half in the last column
+        assert_eq!(cells(Vec2::new(right - 10.0, 0.0), 20.0, 20.0), vec![(2, 0), (3, 0)]);
+        assert!(grid.overlaps_solid(Vec2::new(right - 10.0, 0.0), 20.0, 20.0));
+
+        // wholly outside, to the right and below
+        assert!(!grid.overlaps_solid(Vec2::new(right + 10.0, 0.0), 20.0, 20.0));
+        assert!(!grid.overlaps_solid(Vec2::new(TILE_SIZE * 2.0, bottom + 10.0), 20.0, 20.0));
+        assert!(!grid.is_solid(3, 0));
+        assert!(!grid.is_solid(2, 2));
+    }
+
+    #[test]
+    fn sliding_along_a_wall_keeps_the_other_axis() {
+        let grid = CollisionGrid::from_rows(&[
+            "..#.",
+            "..#.",
+            "..#.",
+            "..#.",
+        ]);
+        let size = Vec2::new(16.0, 16.0);
+
+        let result = move_and_slide(&grid, Vec2::new(40.0, 40.0), size, Vec2::new(20.0, 10.0));
+        assert_eq!(result.position, Vec2::new(48.0, 50.0));
+        assert!(result.blocked_x);
+        assert!(!result.blocked_y);
+
+        // already flush, only the motion along the wall is left
+        let result = move_and_slide(&grid, Vec2::new(48.0, 50.0), size, Vec2::new(5.0, -12.0));
+        assert_eq!(result.position, Vec2::new(48.0, 38.0));
+        assert!(result.blocked_x);
+    }
+
+    #[test]
+    fn a_corner_stops_both_axes() {
+        let grid = CollisionGrid::from_rows(&[
+            "..#",
+            "..#",
+            "###",
+        ]);
+
+        let result = move_and_slide(&grid, Vec2::new(40.0, 40.0), Vec2::new(16.0, 16.0), Vec2::new(20.0, 20.0));
+        assert_eq!(result.position, Vec2::new(48.0, 48.0));
+        assert!(result.blocked_x);
+        assert!(result.blocked_y);
+    }
+
+    #[test]
+    fn fast_motion_does_not_tunnel_through_walls() {
+        let grid = CollisionGrid::from_rows(&[
+            "...#....",
+        ]);
+
+        let result = move_and_slide(&grid, Vec2::new(0.0, 0.0), Vec2::new(16.0, 16.0), Vec2::new(200.0, 0.0));
+        assert_eq!(result.position, Vec2::new(80.0, 0.0));
+        assert!(result.blocked_x);
+    }
+
+    #[test]
+    fn depenetration_takes_the_shortest_way_out() {
+        let grid = CollisionGrid::from_rows(&[
+            "...",
+            ".#.",
+            "...",
+        ]);
+        let size = Vec2::new(16.0, 16.0);
+
+        // 14 pixels into the wall's left side, 24 into its top
+        assert_eq!(depenetrate(&grid, Vec2::new(30.0, 40.0), size), Vec2::new(16.0, 40.0));
+
+        // 6 pixels into its bottom
+        assert_eq!(depenetrate(&grid, Vec2::new(40.0, 58.0), size), Vec2::new(40.0, 64.0));
+
+        // nothing to do outside walls
+        assert_eq!(depenetrate(&grid, Vec2::new(2.0, 2.0), size), Vec2::new(2.0, 2.0));
+    }
+}
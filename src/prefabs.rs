@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::ai::AiParams;
+use crate::components::{Team, WallResponse, PHYSICAL};
+use crate::util::load_asset;
+
+// A prefab lists the components an entity spawns with. Every component is optional, so a
+// new kind of creature is a new entry in the prefab file rather than new code.
+
+#[derive(Deserialize)]
+pub struct SpriteDef {
+    pub name: String,
+    #[serde(default)]
+    pub depth: i32,
+}
+
+fn default_walls() -> WallResponse {
+    WallResponse::Slide
+}
+
+#[derive(Deserialize)]
+pub struct ColliderDef {
+    pub size: [f32; 2],
+    #[serde(default = "default_walls")]
+    pub walls: WallResponse,
+}
+
+#[derive(Deserialize)]
+pub struct InteractionDef {
+    pub prompt: String,
+    // the name of a file in `resources/dialogue`, without `.json`
+    pub dialogue: String,
+}
+
+#[derive(Deserialize)]
+pub struct ProjectileDef {
+    pub damage: i32,
+    #[serde(default = "default_damage_kind")]
+    pub kind: String,
+}
+
+fn default_damage_kind() -> String {
+    String::from(PHYSICAL)
+}
+
+#[derive(Deserialize)]
+pub struct DefenseDef {
+    #[serde(default)]
+    pub armour: i32,
+    // kind of damage -> fraction of it ignored
+    #[serde(default)]
+    pub resistances: HashMap<String, f32>,
+}
+
+fn default_knockback_ms() -> u64 {
+    150
+}
+
+#[derive(Deserialize)]
+pub struct HurtDef {
+    #[serde(default)]
+    pub invulnerable_ms: u64,
+    // pixels per second
+    #[serde(default)]
+    pub knockback: f32,
+    #[serde(default = "default_knockback_ms")]
+    pub knockback_ms: u64,
+}
+
+fn default_respawn_ms() -> u64 {
+    2000
+}
+
+#[derive(Deserialize)]
+pub struct RespawnDef {
+    #[serde(default = "default_respawn_ms")]
+    pub delay_ms: u64,
+    // how many times it comes back, forever if missing
+    #[serde(default)]
+    pub lives: Option<u32>,
+}
+
+fn default_max_attacks() -> usize {
+    6
+}
+
+#[derive(Deserialize)]
+pub struct PlayerControlDef {
+    pub attack: String,
+    pub attack_speed: f32,
+    #[serde(default = "default_max_attacks")]
+    pub max_attacks: usize,
+}
+
+#[derive(Deserialize)]
+pub struct Prefab {
+    #[serde(default)]
+    pub sprite: Option<SpriteDef>,
+    #[serde(default)]
+    pub collider: Option<ColliderDef>,
+    #[serde(default)]
+    pub health: Option<i32>,
+    #[serde(default)]
+    pub defense: Option<DefenseDef>,
+    #[serde(default)]
+    pub hurt: Option<HurtDef>,
+    #[serde(default)]
+    pub respawn: Option<RespawnDef>,
+    #[serde(default)]
+    pub checkpoint: bool,
+    #[serde(default)]
+    pub team: Option<Team>,
+    #[serde(default)]
+    pub ai: Option<AiParams>,
+    #[serde(default)]
+    pub interaction: Option<InteractionDef>,
+    #[serde(default)]
+    pub projectile: Option<ProjectileDef>,
+    #[serde(default)]
+    pub contact_damage: Option<i32>,
+    #[serde(default)]
+    pub player_control: Option<PlayerControlDef>,
+}
+
+#[derive(Deserialize)]
+struct PrefabFile {
+    prefabs: HashMap<String, Prefab>,
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Json(String),
+    UnknownPrefab { name: String, used_by: String },
+    UnknownDialogue { name: String, prefab: String },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::Json(message) => write!(f, "invalid prefabs: {}", message),
+            PrefabError::UnknownPrefab { name, used_by } => {
+                write!(f, "{} uses prefab `{}`, which doesn't exist", used_by, name)
+            }
+            PrefabError::UnknownDialogue { name, prefab } => {
+                write!(f, "prefab `{}` talks with dialogue `{}`, which doesn't exist", prefab, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl Prefabs {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Prefabs> {
+        let path = path.as_ref();
+
+        load_asset(path, Prefabs::parse)
+    }
+
+    pub fn parse(source: &str) -> Result<Prefabs, PrefabError> {
+        let file: PrefabFile = serde_json::from_str(source).map_err(|error| PrefabError::Json(error.to_string()))?;
+
+        for (name, prefab) in &file.prefabs {
+            if let Some(control) = &prefab.player_control {
+                if !file.prefabs.contains_key(&control.attack) {
+                    return Err(PrefabError::UnknownPrefab {
+                        name: control.attack.clone(),
+                        used_by: format!("the attack of `{}`", name),
+                    });
+                }
+            }
+        }
+
+        Ok(Prefabs { prefabs: file.prefabs })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use tetra::graphics::{self, Color, DrawParams, Text};
+use tetra::input::{self, GamepadButton, Key};
+use tetra::math::Vec2;
+use tetra::{window, Context, Event, State};
+
+use crate::assets::Assets;
+use crate::GameState;
+
+const FONT: &str = "./resources/prstart.ttf";
+
+// how long the game keeps running after the player's last death before the game over
+// screen comes up
+pub const GAME_OVER_DELAY: Duration = Duration::from_millis(1500);
+
+// What a scene wants done with the stack after it has updated or handled an event.
+pub enum Transition {
+    None,
+    // puts a scene on top, the ones below stop updating until it is popped
+    Push(Box<dyn Scene>),
+    Pop,
+    // swaps the top scene for another
+    Replace(Box<dyn Scene>),
+    // throws the whole stack away and starts again from one scene
+    Reset(Box<dyn Scene>),
+    Quit,
+}
+
+// One screen of the game: the title, gameplay, the pause menu, ... Only the top scene of
+// the stack updates and gets input, so gameplay under a pause menu stays exactly as it was.
+pub trait Scene {
+    fn update(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result<Transition>;
+
+    fn draw(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result;
+
+    fn event(&mut self, _ctx: &mut Context, _event: &Event) -> Transition {
+        Transition::None
+    }
+
+    // whether the scene below shows through, for menus drawn over the game
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+// Runs a stack of scenes as Tetra's `State`. The game quits once the stack is empty.
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    assets: Assets,
+}
+
+impl SceneStack {
+    pub fn new(first: Box<dyn Scene>, assets: Assets) -> SceneStack {
+        SceneStack {
+            scenes: vec![first],
+            assets,
+        }
+    }
+
+    fn apply(&mut self, ctx: &mut Context, transition: Transition) {
+        match transition {
+            Transition::None => {},
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop => {
+                self.scenes.pop();
+            },
+            Transition::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            },
+            Transition::Reset(scene) => {
+                self.scenes.clear();
+                self.scenes.push(scene);
+            },
+            Transition::Quit => self.scenes.clear(),
+        }
+
+        if self.scenes.is_empty() {
+            window::quit(ctx);
+        }
+    }
+}
+
+impl State for SceneStack {
+    fn update(&mut self, ctx: &mut Context) -> tetra::Result {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.update(ctx, &mut self.assets)?,
+            None => return Ok(()),
+        };
+
+        self.apply(ctx, transition);
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
+        graphics::clear(ctx, Color::rgb(0.08, 0.08, 0.08));
+
+        // the top scene, and everything under it that shows through
+        let mut bottom = self.scenes.len().saturating_sub(1);
+        while bottom > 0 && self.scenes[bottom].is_overlay() {
+            bottom -= 1;
+        }
+
+        for scene in &mut self.scenes[bottom..] {
+            graphics::reset_transform_matrix(ctx);
+            scene.draw(ctx, &mut self.assets)?;
+        }
+
+        Ok(())
+    }
+
+    fn event(&mut self, ctx: &mut Context, event: Event) -> tetra::Result {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.event(ctx, &event),
+            None => return Ok(()),
+        };
+
+        self.apply(ctx, transition);
+
+        Ok(())
+    }
+}
+
+fn confirm_pressed(ctx: &Context) -> bool {
+    input::is_key_pressed(ctx, Key::Enter) || input::is_gamepad_button_pressed(ctx, 0, GamepadButton::A)
+}
+
+fn back_pressed(ctx: &Context) -> bool {
+    input::is_key_pressed(ctx, Key::Escape) || input::is_gamepad_button_pressed(ctx, 0, GamepadButton::B)
+}
+
+// Pausing works with the same button that unpauses.
+pub fn pause_pressed(ctx: &Context) -> bool {
+    input::is_key_pressed(ctx, Key::Escape) || input::is_gamepad_button_pressed(ctx, 0, GamepadButton::Start)
+}
+
+// A heading and a few lines of text centred on the screen, over a dark backdrop that
+// covers (`dim` false) or darkens (`dim` true) whatever is below.
+fn draw_menu(ctx: &mut Context, assets: &mut Assets, heading: &str, lines: &[&str], dim: bool) -> tetra::Result {
+    let (width, height) = (window::get_width(ctx) as f32, window::get_height(ctx) as f32);
+
+    let backdrop = assets.white_pixel(ctx)?;
+    let color = if dim { Color::rgba(0.0, 0.0, 0.0, 0.6) } else { Color::rgb(0.08, 0.08, 0.08) };
+    graphics::draw(ctx, &backdrop, DrawParams::new().scale(Vec2::new(width, height)).color(color));
+
+    let font = assets.font(ctx, FONT)?;
+    let mut y = height / 3.0;
+
+    let heading = Text::new(heading, font, 48.0);
+    y += draw_centred(ctx, &heading, width, y) + 64.0;
+
+    for line in lines {
+        let text = Text::new(*line, font, 16.0);
+        y += draw_centred(ctx, &text, width, y) + 24.0;
+    }
+
+    Ok(())
+}
+
+// draws `text` centred across a `width` wide screen at `y` and returns its height
+fn draw_centred(ctx: &mut Context, text: &Text, width: f32, y: f32) -> f32 {
+    let (text_width, text_height) = text.get_bounds(ctx).map_or((0.0, 0.0), |bounds| (bounds.width, bounds.height));
+    graphics::draw(ctx, text, Vec2::new(((width - text_width) / 2.0).round(), y.round()));
+
+    text_height
+}
+
+fn new_game(ctx: &mut Context, assets: &mut Assets) -> tetra::Result<Box<dyn Scene>> {
+    Ok(Box::new(GameState::new(ctx, assets)?))
+}
+
+pub struct TitleScene;
+
+impl Scene for TitleScene {
+    fn update(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result<Transition> {
+        if confirm_pressed(ctx) {
+            return Ok(Transition::Replace(new_game(ctx, assets)?));
+        }
+
+        if back_pressed(ctx) {
+            return Ok(Transition::Quit);
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result {
+        draw_menu(ctx, assets, "HYPOTERRA", &["Press Enter to start", "Escape to quit"], false)
+    }
+}
+
+pub struct PauseScene;
+
+impl Scene for PauseScene {
+    fn update(&mut self, ctx: &mut Context, _assets: &mut Assets) -> tetra::Result<Transition> {
+        if pause_pressed(ctx) || confirm_pressed(ctx) {
+            return Ok(Transition::Pop);
+        }
+
+        if input::is_key_pressed(ctx, Key::Q) || input::is_gamepad_button_pressed(ctx, 0, GamepadButton::Back) {
+            return Ok(Transition::Reset(Box::new(TitleScene)));
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result {
+        draw_menu(ctx, assets, "PAUSED", &["Escape or Enter to resume", "Q to quit to the title screen"], true)
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
+
+pub struct GameOverScene;
+
+impl Scene for GameOverScene {
+    fn update(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result<Transition> {
+        if confirm_pressed(ctx) {
+            return Ok(Transition::Reset(new_game(ctx, assets)?));
+        }
+
+        if back_pressed(ctx) {
+            return Ok(Transition::Reset(Box::new(TitleScene)));
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &mut Assets) -> tetra::Result {
+        draw_menu(ctx, assets, "GAME OVER", &["Enter to try again", "Escape for the title screen"], true)
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
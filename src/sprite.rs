@@ -0,0 +1,31 @@
+use tetra::graphics::{self, DrawParams, Rectangle};
+use tetra::math::Vec2;
+use tetra::Context;
+
+use hypoterra::animation::Animations;
+use hypoterra::components::Sprite;
+
+use crate::assets::Assets;
+
+// Draws the current frame of a sprite's animation. Sprites without an animation set are
+// skipped.
+pub fn draw_sprite(
+    ctx: &mut Context,
+    assets: &mut Assets,
+    animations: &Animations,
+    sprite: &Sprite,
+    position: Vec2<f32>,
+) -> tetra::Result {
+    let clip = match animations.get(&sprite.name).and_then(|set| set.clip_for(sprite.state, sprite.direction)) {
+        Some(clip) => clip,
+        None => return Ok(()),
+    };
+
+    let texture = assets.texture(ctx, &clip.texture)?;
+    let frame = clip.frame_at(sprite.animation_time);
+    let region = Rectangle::new(frame.region.x, frame.region.y, frame.region.width, frame.region.height);
+
+    graphics::draw(ctx, &texture, DrawParams::new().position(position + frame.offset).clip(region));
+
+    Ok(())
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use vek::Vec2;
+
+use crate::animation::Frame;
+use crate::geometry::Rectangle;
+
+// Sprite sheets exported as JSON by Aseprite ("Export Sprite Sheet" with JSON data) or
+// TexturePacker (the JSON hash and JSON array formats). Both write a list of frames and
+// the image they are packed into. Aseprite also writes frame durations, tags and slices.
+
+#[derive(Deserialize, Clone, Copy)]
+struct RectDef {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+impl RectDef {
+    fn to_rectangle(self) -> Rectangle {
+        Rectangle::new(self.x, self.y, self.w, self.h)
+    }
+}
+
+#[derive(Deserialize)]
+struct PointDef {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameDef {
+    // only written in the array format, the hash format uses it as the key
+    #[serde(default)]
+    filename: String,
+    frame: RectDef,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(default)]
+    trimmed: bool,
+    // where the trimmed frame sits inside the untrimmed one
+    #[serde(default)]
+    sprite_source_size: Option<RectDef>,
+    // milliseconds, Aseprite only
+    #[serde(default)]
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FramesDef {
+    Array(Vec<FrameDef>),
+    Hash(HashMap<String, FrameDef>),
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum TagDirection {
+    #[default]
+    #[serde(rename = "forward")]
+    Forward,
+    #[serde(rename = "reverse")]
+    Reverse,
+    #[serde(rename = "pingpong")]
+    PingPong,
+    #[serde(rename = "pingpong_reverse")]
+    PingPongReverse,
+}
+
+#[derive(Deserialize)]
+struct TagDef {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: TagDirection,
+    // how many times the tag plays, missing or "0" means forever
+    #[serde(default)]
+    repeat: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SliceKeyDef {
+    bounds: RectDef,
+    #[serde(default)]
+    pivot: Option<PointDef>,
+}
+
+#[derive(Deserialize)]
+struct SliceDef {
+    name: String,
+    keys: Vec<SliceKeyDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetaDef {
+    image: String,
+    #[serde(default)]
+    frame_tags: Vec<TagDef>,
+    #[serde(default)]
+    slices: Vec<SliceDef>,
+}
+
+#[derive(Deserialize)]
+struct SheetDef {
+    frames: FramesDef,
+    meta: MetaDef,
+}
+
+// A named run of frames: an Aseprite tag, or frames whose names only differ in their
+// trailing number (`walk_left_0.png`, `walk_left_1.png`, ...).
+pub struct SheetAnimation {
+    pub name: String,
+    pub frames: Vec<Frame>,
+    // Some(false) for Aseprite tags with a repeat count
+    pub looping: Option<bool>,
+}
+
+// A named region of the sprite drawn in Aseprite, e.g. a hitbox, relative to the top left
+// of a frame. Only the first key is used.
+pub struct Slice {
+    pub name: String,
+    pub bounds: Rectangle,
+    pub pivot: Option<Vec2<f32>>,
+}
+
+pub struct SpriteSheet {
+    // the packed image, relative to the working directory like every other texture path
+    pub image: String,
+    pub animations: Vec<SheetAnimation>,
+    pub slices: Vec<Slice>,
+}
+
+#[derive(Debug)]
+pub enum SheetError {
+    Json(String),
+    NoFrames,
+    RotatedFrame(String),
+    TagOutOfRange { tag: String, to: usize, frames: usize },
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SheetError::Json(message) => write!(f, "invalid sprite sheet: {}", message),
+            SheetError::NoFrames => write!(f, "sprite sheet has no frames"),
+            SheetError::RotatedFrame(name) => {
+                write!(f, "frame `{}` is rotated, export the sheet with rotation turned off", name)
+            }
+            SheetError::TagOutOfRange { tag, to, frames } => {
+                write!(f, "tag `{}` ends at frame {} but the sheet has {} frames", tag, to, frames)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SheetError {}
+
+// Splits `walk_left_01.png` into ("walk_left", Some(1)) and `beer 3.aseprite` into
+// ("beer", Some(3)). Names without a trailing number are returned whole.
+fn split_frame_name(name: &str) -> (&str, Option<u32>) {
+    let stem = match name.rfind('.') {
+        Some(dot) if dot > 0 => &name[..dot],
+        _ => name,
+    };
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = stem[prefix.len()..].parse().ok();
+
+    if number.is_none() {
+        return (stem, None);
+    }
+
+    (prefix.trim_end_matches(['_', '-', ' ', '.']), number)
+}
+
+// The frame indices a tag plays, in order. Ping-pong tags don't repeat their end frames.
+fn tag_indices(from: usize, to: usize, direction: TagDirection) -> Vec<usize> {
+    let forward: Vec<usize> = (from..=to).collect();
+    let reverse: Vec<usize> = forward.iter().rev().copied().collect();
+    let inner = |frames: &[usize]| frames[1..frames.len().saturating_sub(1).max(1)].to_vec();
+
+    match direction {
+        TagDirection::Forward => forward,
+        TagDirection::Reverse => reverse,
+        TagDirection::PingPong => [forward, inner(&reverse)].concat(),
+        TagDirection::PingPongReverse => [reverse, inner(&forward)].concat(),
+    }
+}
+
+impl SpriteSheet {
+    // `path` is where the JSON file was read from, the image path in it is relative to
+    // that file. Frames without a duration are shown for `frame_ms` milliseconds.
+    pub fn parse(source: &str, path: &Path, frame_ms: u64) -> Result<SpriteSheet, SheetError> {
+        let sheet: SheetDef = serde_json::from_str(source).map_err(|error| SheetError::Json(error.to_string()))?;
+
+        let mut defs = match sheet.frames {
+            FramesDef::Array(defs) => defs,
+            FramesDef::Hash(defs) => {
+                // the hash format loses the frame order, so sort by name and frame number
+                let mut defs: Vec<FrameDef> = defs
+                    .into_iter()
+                    .map(|(filename, def)| FrameDef { filename, ..def })
+                    .collect();
+                defs.sort_by(|a, b| {
+                    let (a_prefix, a_number) = split_frame_name(&a.filename);
+                    let (b_prefix, b_number) = split_frame_name(&b.filename);
+                    (a_prefix, a_number, &a.filename).cmp(&(b_prefix, b_number, &b.filename))
+                });
+                defs
+            }
+        };
+
+        if defs.is_empty() {
+            return Err(SheetError::NoFrames);
+        }
+
+        let mut frames = Vec::with_capacity(defs.len());
+
+        for def in &mut defs {
+            if def.rotated {
+                return Err(SheetError::RotatedFrame(def.filename.clone()));
+            }
+
+            let offset = match def.sprite_source_size {
+                Some(source) if def.trimmed => Vec2::new(source.x, source.y),
+                _ => Vec2::zero(),
+            };
+
+            frames.push(Frame {
+                region: def.frame.to_rectangle(),
+                duration: Duration::from_millis(def.duration.unwrap_or(frame_ms)),
+                offset,
+            });
+        }
+
+        let mut animations = Vec::new();
+
+        if sheet.meta.frame_tags.is_empty() {
+            for (def, frame) in defs.iter().zip(&frames) {
+                let (name, _) = split_frame_name(&def.filename);
+
+                match animations.iter_mut().find(|animation: &&mut SheetAnimation| animation.name == name) {
+                    Some(animation) => animation.frames.push(frame.clone()),
+                    None => animations.push(SheetAnimation {
+                        name: name.to_string(),
+                        frames: vec![frame.clone()],
+                        looping: None,
+                    }),
+                }
+            }
+        }
+
+        for tag in &sheet.meta.frame_tags {
+            if tag.to >= frames.len() || tag.from > tag.to {
+                return Err(SheetError::TagOutOfRange {
+                    tag: tag.name.clone(),
+                    to: tag.to,
+                    frames: frames.len(),
+                });
+            }
+
+            let repeat: usize = tag.repeat.as_ref().and_then(|repeat| repeat.parse().ok()).unwrap_or(0);
+            let indices = tag_indices(tag.from, tag.to, tag.direction);
+
+            animations.push(SheetAnimation {
+                name: tag.name.clone(),
+                frames: indices.iter().cycle().take(indices.len() * repeat.max(1)).map(|&index| frames[index].clone()).collect(),
+                looping: if repeat == 0 { None } else { Some(false) },
+            });
+        }
+
+        let slices = sheet
+            .meta
+            .slices
+            .into_iter()
+            .filter_map(|slice| {
+                let key = slice.keys.into_iter().next()?;
+
+                Some(Slice {
+                    name: slice.name,
+                    bounds: key.bounds.to_rectangle(),
+                    pivot: key.pivot.map(|pivot| Vec2::new(pivot.x, pivot.y)),
+                })
+            })
+            .collect();
+
+        let image = match path.parent() {
+            Some(directory) => directory.join(&sheet.meta.image).to_string_lossy().into_owned(),
+            None => sheet.meta.image,
+        };
+
+        Ok(SpriteSheet { image, animations, slices })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<SpriteSheet, SheetError> {
+        SpriteSheet::parse(source, Path::new("./resources/sheet.json"), 100)
+    }
+
+    fn animation<'a>(sheet: &'a SpriteSheet, name: &str) -> &'a SheetAnimation {
+        sheet.animations.iter().find(|animation| animation.name == name).unwrap()
+    }
+
+    // the x of every frame, each frame in these sheets is one 16 pixel step further along
+    fn columns(animation: &SheetAnimation) -> Vec<f32> {
+        animation.frames.iter().map(|frame| frame.region.x / 16.0).collect()
+    }
+
+    // An Aseprite array export: four frames, two tags and a hitbox slice.
+    const ASEPRITE: &str = r#"{ "frames": [
+        {"filename": "slime 0.aseprite", "frame": {"x": 0, "y": 0, "w": 16, "h": 16}, "duration": 80},
+        {"filename": "slime 1.aseprite", "frame": {"x": 16, "y": 0, "w": 16, "h": 16}, "duration": 120},
+        {"filename": "slime 2.aseprite", "frame": {"x": 32, "y": 0, "w": 16, "h": 16}, "duration": 80},
+        {"filename": "slime 3.aseprite", "frame": {"x": 48, "y": 0, "w": 16, "h": 16}, "duration": 80}
+    ],
+    "meta": {
+        "image": "slime.png",
+        "frameTags": [
+            {"name": "walk", "from": 0, "to": 3, "direction": "pingpong"},
+            {"name": "attack", "from": 1, "to": 2, "direction": "reverse", "repeat": "2"},
+            {"name": "idle", "from": 0, "to": 0, "repeat": "0"}
+        ],
+        "slices": [
+            {"name": "hitbox", "keys": [{"frame": 0, "bounds": {"x": 2, "y": 4, "w": 12, "h": 10}, "pivot": {"x": 8, "y": 14}}]},
+            {"name": "empty", "keys": []}
+        ]
+    } }"#;
+
+    #[test]
+    fn aseprite_tags_become_animations() {
+        let sheet = parse(ASEPRITE).unwrap();
+
+        // next to the JSON file it was exported with
+        assert_eq!(Path::new(&sheet.image), Path::new("./resources/slime.png"));
+        assert_eq!(sheet.animations.len(), 3);
+
+        // there and back again without showing either end twice in a row
+        let walk = animation(&sheet, "walk");
+        assert_eq!(columns(walk), vec![0.0, 1.0, 2.0, 3.0, 2.0, 1.0]);
+        assert_eq!(walk.looping, None);
+        assert_eq!(walk.frames[1].duration, Duration::from_millis(120));
+
+        // a repeat of 0 is forever
+        assert_eq!(animation(&sheet, "idle").looping, None);
+    }
+
+    #[test]
+    fn ping_pong_tags() {
+        assert_eq!(tag_indices(0, 3, TagDirection::PingPong), vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(tag_indices(0, 3, TagDirection::PingPongReverse), vec![3, 2, 1, 0, 1, 2]);
+        assert_eq!(tag_indices(4, 5, TagDirection::PingPong), vec![4, 5]);
+        assert_eq!(tag_indices(2, 2, TagDirection::PingPong), vec![2]);
+        assert_eq!(tag_indices(1, 3, TagDirection::Reverse), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn repeated_tags_play_once() {
+        let sheet = parse(ASEPRITE).unwrap();
+        let attack = animation(&sheet, "attack");
+
+        // backwards, twice over, then it stops
+        assert_eq!(columns(attack), vec![2.0, 1.0, 2.0, 1.0]);
+        assert_eq!(attack.looping, Some(false));
+    }
+
+    #[test]
+    fn slices() {
+        let sheet = parse(ASEPRITE).unwrap();
+
+        // slices without keys are skipped
+        assert_eq!(sheet.slices.len(), 1);
+        assert_eq!(sheet.slices[0].name, "hitbox");
+        assert_eq!(sheet.slices[0].bounds, Rectangle::new(2.0, 4.0, 12.0, 10.0));
+        assert_eq!(sheet.slices[0].pivot, Some(Vec2::new(8.0, 14.0)));
+    }
+
+    #[test]
+    fn texture_packer_hash_sorts_by_trailing_number() {
+        let source = r#"{ "frames": {
+            "walk_10.png": {"frame": {"x": 160, "y": 0, "w": 16, "h": 16}},
+            "idle.png": {"frame": {"x": 0, "y": 16, "w": 16, "h": 16}},
+            "walk_2.png": {"frame": {"x": 32, "y": 0, "w": 16, "h": 16}},
+            "walk_1.png": {"frame": {"x": 16, "y": 0, "w": 16, "h": 16}}
+        },
+        "meta": { "image": "sheet.png" } }"#;
+        let sheet = parse(source).unwrap();
+
+        assert_eq!(sheet.animations.len(), 2);
+
+        // by number, not by name, where `walk_10` would come before `walk_2`
+        let walk = animation(&sheet, "walk");
+        assert_eq!(columns(walk), vec![1.0, 2.0, 10.0]);
+        assert_eq!(walk.looping, None);
+
+        // without durations in the sheet, the default is used
+        assert!(walk.frames.iter().all(|frame| frame.duration == Duration::from_millis(100)));
+        assert_eq!(animation(&sheet, "idle").frames.len(), 1);
+    }
+
+    #[test]
+    fn frame_names() {
+        assert_eq!(split_frame_name("walk_left_01.png"), ("walk_left", Some(1)));
+        assert_eq!(split_frame_name("beer 3.aseprite"), ("beer", Some(3)));
+        assert_eq!(split_frame_name("walk-10"), ("walk", Some(10)));
+        assert_eq!(split_frame_name("idle.png"), ("idle", None));
+        assert_eq!(split_frame_name(".hidden"), (".hidden", None));
+    }
+
+    #[test]
+    fn trimmed_frames_keep_their_offset() {
+        let source = r#"{ "frames": [
+            {"filename": "die_0.png", "frame": {"x": 0, "y": 0, "w": 10, "h": 12}, "trimmed": true,
+             "spriteSourceSize": {"x": 3, "y": 4, "w": 10, "h": 12}, "sourceSize": {"w": 16, "h": 16}},
+            {"filename": "die_1.png", "frame": {"x": 10, "y": 0, "w": 16, "h": 16}, "trimmed": false,
+             "spriteSourceSize": {"x": 5, "y": 5, "w": 16, "h": 16}, "sourceSize": {"w": 16, "h": 16}}
+        ],
+        "meta": { "image": "sheet.png" } }"#;
+        let sheet = parse(source).unwrap();
+        let die = animation(&sheet, "die");
+
+        assert_eq!(die.frames[0].region, Rectangle::new(0.0, 0.0, 10.0, 12.0));
+        assert_eq!(die.frames[0].offset, Vec2::new(3.0, 4.0));
+
+        // only trimmed frames are moved
+        assert_eq!(die.frames[1].offset, Vec2::zero());
+    }
+
+    #[test]
+    fn errors() {
+        let rotated = r#"{ "frames": [
+            {"filename": "walk_0.png", "frame": {"x": 0, "y": 0, "w": 16, "h": 16}, "rotated": true}
+        ],
+        "meta": { "image": "sheet.png" } }"#;
+        assert!(matches!(parse(rotated), Err(SheetError::RotatedFrame(ref name)) if name == "walk_0.png"));
+
+        let out_of_range = ASEPRITE.replace(r#""from": 1, "to": 2"#, r#""from": 1, "to": 4"#);
+        assert!(matches!(
+            parse(&out_of_range),
+            Err(SheetError::TagOutOfRange { ref tag, to: 4, frames: 4 }) if tag == "attack"
+        ));
+
+        let backwards = ASEPRITE.replace(r#""from": 1, "to": 2"#, r#""from": 2, "to": 1"#);
+        assert!(matches!(parse(&backwards), Err(SheetError::TagOutOfRange { .. })));
+
+        assert!(matches!(parse(r#"{ "frames": [], "meta": { "image": "sheet.png" } }"#), Err(SheetError::NoFrames)));
+        assert!(matches!(parse(r#"{ "frames": [] }"#), Err(SheetError::Json(_))));
+    }
+}
@@ -0,0 +1,1102 @@
+use std::time::Duration;
+
+use vek::Vec2;
+
+use crate::ai::{think, AiState, Senses};
+use crate::animation::AnimationState;
+use crate::camera::{apply_view, clamp_to_level, step_camera, view_for, view_size, CameraState};
+use crate::components::{Sprite, Transform, WallResponse, PHYSICAL};
+use crate::controls::Input;
+use crate::dialogue::Conversation;
+use crate::direction::Direction;
+use crate::ecs::Entity;
+use crate::movement::step_velocity;
+use crate::physics::move_and_slide;
+use crate::timestep::{tick_duration, DT};
+use crate::util::{collision, line_of_sight, raycast};
+use crate::world::World;
+
+// Each system does one job for every entity with the components it cares about.
+// `World::update` runs them in a fixed order.
+
+// where an entity's collider is, as (position, size)
+fn bounds(world: &World, entity: Entity) -> Option<(Vec2<f32>, Vec2<f32>)> {
+    let transform = world.transforms.get(entity)?;
+    let collider = world.colliders.get(entity)?;
+
+    Some((transform.position, collider.size))
+}
+
+fn overlapping(world: &World, first: Entity, second: Entity) -> bool {
+    match (bounds(world, first), bounds(world, second)) {
+        (Some((a, a_size)), Some((b, b_size))) => collision(a, b, a_size.x, a_size.y, b_size.x, b_size.y),
+        _ => false,
+    }
+}
+
+// remembered so draw can blend between this tick and the next one
+pub fn remember_positions(world: &mut World) {
+    world.camera_previous_position = world.camera.position;
+
+    for (_, transform) in world.transforms.iter_mut() {
+        transform.previous_position = transform.position;
+    }
+}
+
+// the middle of the player, what the camera follows
+fn camera_target(world: &World) -> Option<Vec2<f32>> {
+    if !world.player_alive() {
+        return None;
+    }
+
+    bounds(world, world.player).map(|(position, size)| position + size / 2.0)
+}
+
+fn viewport(world: &World) -> Vec2<f32> {
+    Vec2::new(world.camera.viewport_width, world.camera.viewport_height)
+}
+
+// Centres the camera on the player straight away, for the first tick of a level.
+pub fn snap_camera(world: &mut World) {
+    let target = camera_target(world).unwrap_or(world.camera_state.focus);
+    let viewport = viewport(world);
+    let mut state = CameraState::new(target);
+
+    if world.camera_config.clamp_to_level {
+        state.focus = clamp_to_level(target, view_size(viewport, world.camera_config.zoom), world.level_bounds);
+    }
+
+    world.camera_state = state;
+    let view = view_for(state, world.level_bounds, viewport, &world.camera_config);
+    apply_view(&mut world.camera, view);
+}
+
+// Eases the camera after the player and shakes it. Once the player is dead the camera
+// stays where it is but keeps shaking.
+pub fn follow_camera(world: &mut World) {
+    let target = camera_target(world).unwrap_or(world.camera_state.focus);
+    let viewport = viewport(world);
+    let state = step_camera(world.camera_state, target, world.level_bounds, viewport, &world.camera_config, DT);
+
+    world.camera_state = state;
+    let view = view_for(state, world.level_bounds, viewport, &world.camera_config);
+    apply_view(&mut world.camera, view);
+}
+
+// Talking to NPCs. Interacting next to one starts its dialogue, then while the
+// conversation goes on interacting finishes typing the page, turns it or picks the
+// highlighted choice, and the menu keys move the highlight.
+pub fn interaction(world: &mut World, input: &Input) {
+    if world.conversation.is_some() {
+        converse(world, input);
+    } else {
+        let nearby = world
+            .interactions
+            .iter()
+            .find(|(entity, _)| overlapping(world, world.player, *entity))
+            .map(|(_, interaction)| (interaction.prompt.clone(), interaction.dialogue.clone()));
+
+        match nearby {
+            Some((prompt, dialogue)) => {
+                world.help_menu.visible = true;
+                world.help_menu.text = prompt;
+
+                if input.interact {
+                    if let Some(found) = world.dialogues.get(&dialogue) {
+                        world.conversation = Conversation::start(&dialogue, found, &mut world.story);
+                        open_page(world);
+                    }
+                }
+            },
+            None => {
+                world.help_menu.visible = false;
+            },
+        }
+    }
+
+    world.dialogue_box.update(tick_duration());
+}
+
+fn converse(world: &mut World, input: &Input) {
+    let conversation = match &mut world.conversation {
+        Some(conversation) => conversation,
+        None => return,
+    };
+    let dialogue = match world.dialogues.get(&conversation.dialogue) {
+        Some(dialogue) => dialogue,
+        None => {
+            world.conversation = None;
+            world.dialogue_box.close();
+            return;
+        },
+    };
+
+    if world.dialogue_box.choices_shown() {
+        if input.menu_up {
+            conversation.select(-1, dialogue, &world.story);
+        }
+        if input.menu_down {
+            conversation.select(1, dialogue, &world.story);
+        }
+
+        world.dialogue_box.selected = conversation.selected;
+    }
+
+    // the box gets to finish its own pages first
+    if !input.interact || world.dialogue_box.advance() {
+        return;
+    }
+
+    if conversation.advance(dialogue, &mut world.story) {
+        open_page(world);
+    } else {
+        world.conversation = None;
+        world.dialogue_box.close();
+    }
+}
+
+// Starts typing the conversation's current page, with its choices.
+fn open_page(world: &mut World) {
+    let page = world.conversation.as_ref().and_then(|conversation| {
+        let dialogue = world.dialogues.get(&conversation.dialogue)?;
+        let choices = conversation
+            .choices(dialogue, &world.story)
+            .iter()
+            .map(|choice| choice.text.clone())
+            .collect::<Vec<String>>();
+
+        Some((conversation.text(dialogue).to_string(), choices, conversation.has_more(dialogue, &world.story)))
+    });
+
+    match page {
+        Some((text, choices, continues)) => {
+            world.help_menu.visible = false;
+            world.dialogue_box.open(&text, choices, continues);
+        },
+        None => world.dialogue_box.close(),
+    }
+}
+
+// Projectiles stop at the first collidable tile or once they leave the screen.
+pub fn cull_projectiles(world: &mut World) {
+    let mut spent: Vec<Entity> = Vec::new();
+
+    for (entity, collider) in world.colliders.iter() {
+        if collider.walls != WallResponse::Despawn {
+            continue;
+        }
+
+        // `movement` stops these where they hit a wall
+        if collider.blocked_x || collider.blocked_y {
+            spent.push(entity);
+        }
+    }
+
+    for (entity, _) in world.projectiles.iter() {
+        if !world.is_visible(entity) {
+            spent.push(entity);
+        }
+    }
+
+    for entity in spent {
+        world.despawn(entity);
+    }
+}
+
+// the middle of an entity's collider, or its position without one
+fn center(world: &World, entity: Entity) -> Option<Vec2<f32>> {
+    let transform = world.transforms.get(entity)?;
+    let size = world.colliders.get(entity).map_or(Vec2::zero(), |collider| collider.size);
+
+    Some(transform.position + size / 2.0)
+}
+
+// Takes `amount` of `kind` damage, less the entity's armour and resistance, off its health
+// unless it is still invulnerable from its last hit, and knocks it away from `from`. Hits
+// without a `from`, like a damaging tile, hurt without knocking anything back. Hits that
+// armour soaks up completely do nothing. What dies stays around until `remove_dead` or
+// `respawn` deals with it.
+fn damage(world: &mut World, entity: Entity, amount: i32, kind: &str, from: Option<Vec2<f32>>) {
+    if world.hurt_responses.get(entity).is_some_and(|hurt| hurt.invulnerable > Duration::from_secs(0)) {
+        return;
+    }
+
+    let amount = world.defenses.get(entity).map_or(amount, |defense| defense.reduce(amount, kind));
+    if amount <= 0 {
+        return;
+    }
+
+    let away = match (center(world, entity), from) {
+        (Some(center), Some(from)) if center != from => Some((center - from).normalized()),
+        (_, Some(_)) => Some(Vec2::zero()),
+        (_, None) => None,
+    };
+
+    let health = match world.healths.get_mut(entity) {
+        Some(health) => health,
+        None => return,
+    };
+
+    if health.current <= 0 {
+        return;
+    }
+
+    health.current = (health.current - amount).max(0);
+    let dead = health.current == 0;
+
+    if dead {
+        if let Some(respawn) = world.respawns.get_mut(entity) {
+            if respawn.lives != Some(0) {
+                respawn.dead_for = Some(Duration::from_secs(0));
+                respawn.lives = respawn.lives.map(|lives| lives - 1);
+            }
+        }
+
+        if let Some(velocity) = world.velocities.get_mut(entity) {
+            velocity.0 = Vec2::zero();
+        }
+    } else {
+        if let Some(sprite) = world.sprites.get_mut(entity) {
+            sprite.trigger = Some(AnimationState::Hurt);
+        }
+
+        if let Some(hurt) = world.hurt_responses.get_mut(entity) {
+            hurt.invulnerable = hurt.invulnerable_for;
+
+            if let Some(away) = away {
+                hurt.knocked_back = hurt.knockback_for;
+
+                if let Some(velocity) = world.velocities.get_mut(entity) {
+                    velocity.0 = away * hurt.knockback;
+                }
+            }
+        }
+    }
+
+    if dead {
+        world.add_trauma(world.camera_config.kill_trauma);
+    } else if entity == world.player {
+        world.add_trauma(world.camera_config.hit_trauma);
+    }
+}
+
+// whether `source` is allowed to hurt `target`
+fn hostile(world: &World, source: Entity, target: Entity) -> bool {
+    source != target && world.teams.get(source) != world.teams.get(target)
+}
+
+pub fn combat(world: &mut World) {
+    // each projectile hits the first thing it overlaps and is destroyed with the hit
+    let mut hits: Vec<(Entity, Entity)> = Vec::new();
+
+    for (projectile, _) in world.projectiles.iter() {
+        if world.entities.is_despawning(projectile) {
+            continue;
+        }
+
+        for (target, health) in world.healths.iter() {
+            if health.current <= 0 || world.entities.is_despawning(target) || !hostile(world, projectile, target) {
+                continue;
+            }
+
+            // both in world space, wherever the camera is
+            if overlapping(world, projectile, target) {
+                hits.push((projectile, target));
+
+                break;
+            }
+        }
+    }
+
+    for (projectile, target) in hits {
+        // an earlier hit this tick may already have finished the target off, then this
+        // projectile keeps flying
+        if world.healths.get(target).is_none_or(|health| health.current <= 0) {
+            continue;
+        }
+
+        let (amount, kind) = match world.projectiles.get(projectile) {
+            Some(projectile) => (projectile.damage, projectile.kind.clone()),
+            None => continue,
+        };
+        let from = center(world, projectile);
+        damage(world, target, amount, &kind, from);
+        world.despawn(projectile);
+    }
+
+    // anything touching a living contact damage entity of another team is hurt every tick
+    let mut touches: Vec<(Entity, i32, String, Option<Vec2<f32>>)> = Vec::new();
+
+    for (source, contact) in world.contact_damages.iter() {
+        if world.entities.is_despawning(source) || is_dead(world, source) {
+            continue;
+        }
+
+        for (target, _) in world.healths.iter() {
+            if hostile(world, source, target) && overlapping(world, source, target) {
+                touches.push((target, contact.damage, contact.kind.clone(), center(world, source)));
+            }
+        }
+    }
+
+    for (target, amount, kind, from) in touches {
+        damage(world, target, amount, &kind, from);
+    }
+
+    // and anything standing on a damaging tile, without being knocked back by it
+    let mut burns: Vec<(Entity, i32)> = Vec::new();
+
+    for (target, _) in world.healths.iter() {
+        if let Some((position, size)) = bounds(world, target) {
+            let amount = world.collision_grid.damage_overlapping(position, size.x, size.y);
+            if amount > 0 {
+                burns.push((target, amount));
+            }
+        }
+    }
+
+    for (target, amount) in burns {
+        damage(world, target, amount, PHYSICAL, None);
+    }
+}
+
+fn is_dead(world: &World, entity: Entity) -> bool {
+    world.healths.get(entity).is_some_and(|health| health.current <= 0)
+}
+
+// Despawns whatever has died and won't respawn (or has run out of lives) once its death
+// animation has played, or straight away if it has none. The player always stays.
+pub fn remove_dead(world: &mut World) {
+    let mut dead: Vec<Entity> = Vec::new();
+
+    for (entity, health) in world.healths.iter() {
+        let respawning = world.respawns.get(entity).is_some_and(|respawn| respawn.dead_for.is_some());
+
+        if health.current > 0 || entity == world.player || respawning {
+            continue;
+        }
+
+        let dying = world.sprites.get(entity).is_some_and(|sprite| {
+            world.animations.get(&sprite.name).is_some_and(|set| match set.find(AnimationState::Die, sprite.direction) {
+                // the animation system switches to the clip the tick after the killing blow
+                Some(clip) => sprite.state != AnimationState::Die || !clip.finished(sprite.animation_time),
+                None => false,
+            })
+        });
+
+        if !dying {
+            dead.push(entity);
+        }
+    }
+
+    for entity in dead {
+        world.despawn(entity);
+    }
+}
+
+// Counts down invulnerability and knockback.
+pub fn recover(world: &mut World) {
+    let tick = tick_duration();
+
+    for (_, hurt) in world.hurt_responses.iter_mut() {
+        hurt.invulnerable = hurt.invulnerable.checked_sub(tick).unwrap_or_default();
+        hurt.knocked_back = hurt.knocked_back.checked_sub(tick).unwrap_or_default();
+    }
+}
+
+// Brings dead entities that respawn back to life at their respawn point once their delay
+// is up, briefly invulnerable so they aren't killed again straight away.
+pub fn respawn(world: &mut World) {
+    let tick = tick_duration();
+    let mut revived: Vec<(Entity, Vec2<f32>)> = Vec::new();
+
+    for (entity, respawn) in world.respawns.iter_mut() {
+        if let Some(dead_for) = respawn.dead_for {
+            if dead_for >= respawn.delay {
+                respawn.dead_for = None;
+                revived.push((entity, respawn.point));
+            } else {
+                respawn.dead_for = Some(dead_for + tick);
+            }
+        }
+    }
+
+    for (entity, point) in revived {
+        if let Some(health) = world.healths.get_mut(entity) {
+            health.current = health.max;
+        }
+
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            transform.position = point;
+            transform.previous_position = point;
+        }
+
+        if let Some(velocity) = world.velocities.get_mut(entity) {
+            velocity.0 = Vec2::zero();
+        }
+
+        if let Some(hurt) = world.hurt_responses.get_mut(entity) {
+            hurt.invulnerable = hurt.invulnerable_for;
+            hurt.knocked_back = Duration::from_secs(0);
+        }
+
+        if let Some(sprite) = world.sprites.get_mut(entity) {
+            sprite.visible = true;
+            sprite.state = AnimationState::Idle;
+            sprite.trigger = None;
+            sprite.animation_time = Duration::from_secs(0);
+        }
+    }
+}
+
+// Moves the respawn point of anything that respawns to the last checkpoint it touched,
+// centred on the checkpoint.
+pub fn checkpoints(world: &mut World) {
+    let mut reached: Vec<(Entity, Entity)> = Vec::new();
+
+    for (checkpoint, _) in world.checkpoints.iter() {
+        for (entity, respawn) in world.respawns.iter() {
+            if respawn.dead_for.is_none() && overlapping(world, checkpoint, entity) {
+                reached.push((checkpoint, entity));
+            }
+        }
+    }
+
+    for (checkpoint, entity) in reached {
+        let size = world.colliders.get(entity).map_or(Vec2::zero(), |collider| collider.size);
+        let point = match center(world, checkpoint) {
+            Some(center) => center - size / 2.0,
+            None => continue,
+        };
+
+        if let Some(respawn) = world.respawns.get_mut(entity) {
+            respawn.point = point;
+        }
+
+        if let Some(checkpoint) = world.checkpoints.get_mut(checkpoint) {
+            checkpoint.reached = true;
+        }
+    }
+}
+
+pub fn ai(world: &mut World) {
+    let tick = tick_duration();
+    let player = if world.player_alive() { bounds(world, world.player) } else { None };
+
+    for (entity, ai) in world.ais.iter_mut() {
+        // being knocked back, it gets its legs back when that's over
+        if world.hurt_responses.get(entity).is_some_and(|hurt| hurt.knocked_back > Duration::from_secs(0)) {
+            continue;
+        }
+
+        // the dead lie still while their death animation plays
+        if world.healths.get(entity).is_some_and(|health| health.current <= 0) {
+            if let Some(velocity) = world.velocities.get_mut(entity) {
+                velocity.0 = Vec2::zero();
+            }
+            continue;
+        }
+
+        let (position, size) = match (world.transforms.get(entity), world.colliders.get(entity)) {
+            (Some(transform), Some(collider)) => (transform.position, collider.size),
+            (Some(transform), None) => (transform.position, Vec2::zero()),
+            _ => continue,
+        };
+
+        let target = player.map(|(player_position, player_size)| player_position + player_size / 2.0 - size / 2.0);
+
+        // paths are only looked for when the state machine could use them
+        let toward_target = match target {
+            Some(target) if target.distance(position) <= ai.params.lose_sight_radius => {
+                world.pathfinder.next_waypoint(position, target, size)
+            },
+            _ => None,
+        };
+        let toward_post = match ai.state {
+            AiState::Return => world.pathfinder.next_waypoint(position, ai.post, size),
+            _ => None,
+        };
+
+        // looking from centre to centre
+        let in_sight = match target {
+            Some(target) => line_of_sight(&world.collision_grid, position + size / 2.0, target + size / 2.0),
+            None => false,
+        };
+
+        let senses = Senses {
+            position,
+            target,
+            in_sight,
+            toward_target,
+            toward_post,
+            blocked: world.colliders.get(entity).is_some_and(|collider| collider.blocked_x),
+            health: world
+                .healths
+                .get(entity)
+                .map_or(1.0, |health| health.current as f32 / health.max.max(1) as f32),
+        };
+        let previous_state = ai.state;
+        let velocity = think(ai, &senses, tick);
+
+        if ai.state == AiState::Attack && previous_state != AiState::Attack {
+            if let Some(sprite) = world.sprites.get_mut(entity) {
+                sprite.trigger = Some(AnimationState::Attack);
+            }
+        }
+
+        if let Some(current) = world.velocities.get_mut(entity) {
+            current.0 = velocity;
+        }
+    }
+}
+
+pub fn player_control(world: &mut World, input: &Input) {
+    // the player stands still while talking
+    let move_input = if world.conversation.is_some() { Vec2::zero() } else { input.movement };
+
+    for (entity, control) in world.player_controls.iter_mut() {
+        // the dead don't move and the knocked back can't
+        let dead = world.healths.get(entity).is_some_and(|health| health.current <= 0);
+        let knocked_back = world.hurt_responses.get(entity).is_some_and(|hurt| hurt.knocked_back > Duration::from_secs(0));
+        if dead || knocked_back {
+            control.facing = None;
+            continue;
+        }
+
+        // of the ground under the middle of the collider
+        let friction = match (world.transforms.get(entity), world.colliders.get(entity)) {
+            (Some(transform), Some(collider)) => world.collision_grid.friction_at(transform.position + collider.size / 2.0),
+            _ => 1.0,
+        };
+
+        let velocity = match world.velocities.get_mut(entity) {
+            Some(velocity) => velocity,
+            None => continue,
+        };
+
+        velocity.0 = step_velocity(velocity.0, move_input, &world.movement_config, friction, DT);
+
+        control.facing = Direction::from_vector(move_input);
+
+        if let Some(facing) = control.facing {
+            control.prev_facing = facing;
+        }
+    }
+}
+
+pub fn movement(world: &mut World) {
+    for (entity, velocity) in world.velocities.iter_mut() {
+        let transform = match world.transforms.get_mut(entity) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        let motion = velocity.0 * DT;
+
+        let collider = match world.colliders.get_mut(entity) {
+            Some(collider) => collider,
+            None => {
+                transform.position += motion;
+                continue;
+            },
+        };
+
+        match collider.walls {
+            WallResponse::Slide => {
+                let result = move_and_slide(&world.collision_grid, transform.position, collider.size, motion);
+                transform.position = result.position;
+                collider.blocked_x = result.blocked_x;
+                collider.blocked_y = result.blocked_y;
+
+                // running into a wall stops movement along that axis only, so the entity
+                // keeps sliding along it
+                if result.blocked_x {
+                    velocity.0.x = 0.0;
+                }
+                if result.blocked_y {
+                    velocity.0.y = 0.0;
+                }
+            },
+            WallResponse::Turn => {
+                let previous = transform.position;
+                transform.position += motion;
+
+                let blocked = world.collision_grid.overlaps_solid(transform.position, collider.size.x, collider.size.y);
+                if blocked {
+                    transform.position = previous;
+                }
+                collider.blocked_x = blocked && motion.x != 0.0;
+                collider.blocked_y = blocked && motion.y != 0.0;
+            },
+            WallResponse::Despawn => {
+                // A ray from the middle, long enough to reach the leading edge at the end of
+                // the move. On a hit it stops touching the wall and `cull_projectiles`
+                // removes it next tick.
+                let distance = motion.magnitude();
+                let direction = if distance > 0.0 { motion / distance } else { Vec2::zero() };
+                let extent = direction.x.abs() * collider.size.x / 2.0 + direction.y.abs() * collider.size.y / 2.0;
+                let center = transform.position + collider.size / 2.0;
+
+                match raycast(&world.collision_grid, center, motion, distance + extent) {
+                    Some(hit) => {
+                        transform.position += direction * (hit.distance - extent).max(0.0);
+                        collider.blocked_x = hit.normal.x != 0.0 || hit.normal == Vec2::zero();
+                        collider.blocked_y = hit.normal.y != 0.0;
+                    },
+                    None => transform.position += motion,
+                }
+            },
+        }
+    }
+}
+
+pub fn attack(world: &mut World, input: &Input) {
+    if !input.attack || !world.player_alive() || world.conversation.is_some() {
+        return;
+    }
+
+    let mut shots: Vec<(Entity, String, Vec2<f32>, Vec2<f32>)> = Vec::new();
+
+    for (entity, control) in world.player_controls.iter() {
+        let live_attacks = world.entities.values().filter(|info| info.prefab == control.attack).count();
+        if live_attacks >= control.max_attacks {
+            continue;
+        }
+
+        let (position, size) = match bounds(world, entity) {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+
+        // standing still fires the way the player last moved
+        let direction = control.facing.unwrap_or(control.prev_facing);
+
+        shots.push((entity, control.attack.clone(), position + size / 4.0, direction.to_vector() * control.attack_speed));
+    }
+
+    for (shooter, prefab, position, velocity) in shots {
+        if let Some(attack) = world.spawn(&prefab, position) {
+            if let Some(attack_velocity) = world.velocities.get_mut(attack) {
+                attack_velocity.0 = velocity;
+            }
+        }
+
+        if let Some(sprite) = world.sprites.get_mut(shooter) {
+            sprite.trigger = Some(AnimationState::Attack);
+        }
+    }
+}
+
+// Picks each sprite's animation state from what the entity is doing. Dying wins over
+// everything, then a triggered or still playing one-shot (attack, hurt), then walking or
+// standing. States the sprite has no clip for are skipped. Animations run on simulation
+// time, not on how often the game is drawn.
+pub fn animation(world: &mut World) {
+    let tick = tick_duration();
+
+    for (entity, sprite) in world.sprites.iter_mut() {
+        let set = match world.animations.get(&sprite.name) {
+            Some(set) => set,
+            None => continue,
+        };
+        let velocity = world.velocities.get(entity).map_or(Vec2::zero(), |velocity| velocity.0);
+
+        let moving = match world.player_controls.get(entity) {
+            Some(control) => {
+                sprite.direction = control.facing.unwrap_or(control.prev_facing);
+                control.facing.is_some()
+            },
+            None => {
+                // enemies keep looking at what they're facing while they stand still
+                if let Some(ai) = world.ais.get(entity) {
+                    sprite.direction = ai.facing;
+                } else if let Some(direction) = Direction::from_vector(velocity) {
+                    sprite.direction = direction;
+                }
+                velocity != Vec2::zero()
+            },
+        };
+        let dead = world.healths.get(entity).is_some_and(|health| health.current <= 0);
+
+        let playing_one_shot = sprite.state.is_one_shot() &&
+            set.find(sprite.state, sprite.direction).is_some_and(|clip| !clip.finished(sprite.animation_time));
+        let trigger = sprite.trigger.take().filter(|state| set.has(*state));
+
+        let state = if dead {
+            AnimationState::Die
+        } else if let Some(trigger) = trigger {
+            trigger
+        } else if playing_one_shot {
+            sprite.state
+        } else if moving {
+            AnimationState::Walk
+        } else {
+            AnimationState::Idle
+        };
+
+        // a sprite without a death animation just disappears
+        if dead && !set.has(AnimationState::Die) {
+            sprite.visible = false;
+        }
+
+        if state != sprite.state || trigger.is_some() {
+            sprite.state = state;
+            sprite.animation_time = Duration::from_secs(0);
+        } else {
+            sprite.animation_time += tick;
+        }
+    }
+}
+
+// Invulnerable entities blink, hidden for every other BLINK_MS.
+const BLINK_MS: u128 = 80;
+
+fn blinked_out(world: &World, entity: Entity) -> bool {
+    world.hurt_responses.get(entity).is_some_and(|hurt| {
+        hurt.invulnerable > Duration::from_secs(0) && !(hurt.invulnerable.as_millis() / BLINK_MS).is_multiple_of(2)
+    })
+}
+
+// Every visible sprite with where to draw it, back to front. This is what the front-end
+// renders each frame.
+pub fn sprites_to_draw(world: &World) -> Vec<(Entity, &Sprite, &Transform)> {
+    let mut sprites: Vec<(Entity, &Sprite, &Transform)> = world
+        .sprites
+        .iter()
+        .filter(|(_, sprite)| sprite.visible)
+        .filter(|(entity, _)| !blinked_out(world, *entity))
+        .filter_map(|(entity, sprite)| world.transforms.get(entity).map(|transform| (entity, sprite, transform)))
+        .collect();
+
+    // stable, so sprites on the same depth keep spawn order
+    sprites.sort_by_key(|(_, sprite, _)| sprite.depth);
+
+    sprites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{Level, TILE_SIZE};
+    use crate::tiles::TileRegistry;
+    use crate::world::WorldConfig;
+
+    // A world on an open level of floor `size` tiles across, with the game's own prefabs
+    // spawned at the given tiles. One of them has to be the player.
+    fn open_world(size: usize, spawns: &[(&str, usize, usize)]) -> World {
+        let config = WorldConfig::load("./resources", 1280.0, 960.0).unwrap();
+
+        floor_world(config, 4, size, spawns)
+    }
+
+    // the same, with the floor made of tile `floor` of the config's registry
+    fn floor_world(config: WorldConfig, floor: i32, size: usize, spawns: &[(&str, usize, usize)]) -> World {
+        let floor = floor.to_string();
+
+        let mut source = format!("size {} {}\n", size, size);
+        for (prefab, x, y) in spawns {
+            source += &format!("spawn {} {} {}\n", prefab, *x as f32 * TILE_SIZE, *y as f32 * TILE_SIZE);
+        }
+        source += "layer ground\n";
+        for _ in 0..size {
+            source += &vec![floor.as_str(); size].join(" ");
+            source += "\n";
+        }
+        source += "end\n";
+
+        let level = Level::parse(&source, &config.tile_registry).unwrap();
+        World::new(&level, config).unwrap()
+    }
+
+    fn find(world: &World, prefab: &str) -> Entity {
+        world.entities.iter().find(|(_, info)| info.prefab == prefab).map(|(entity, _)| entity).unwrap()
+    }
+
+    fn health(world: &World, entity: Entity) -> i32 {
+        world.healths.get(entity).unwrap().current
+    }
+
+    fn position(world: &World, entity: Entity) -> Vec2<f32> {
+        world.transforms.get(entity).unwrap().position
+    }
+
+    fn run(world: &mut World, ticks: usize) {
+        for _ in 0..ticks {
+            world.update(&Input::default());
+        }
+    }
+
+    // The real 80x80 level, with the player `gap` tiles to one side of an enemy at tile `x`,
+    // `y` and the camera on the player. The enemy is the first one in the level, moved.
+    fn level1_with_enemy_at(x: usize, y: usize, gap: f32) -> (World, Entity) {
+        let config = WorldConfig::load("./resources", 1280.0, 960.0).unwrap();
+        let level = Level::load("./resources/levels/level1.lvl", &config.tile_registry).unwrap();
+        let mut world = World::new(&level, config).unwrap();
+        let enemy = find(&world, "enemy");
+
+        let spot = Vec2::new(x as f32, y as f32) * TILE_SIZE;
+        world.transforms.get_mut(enemy).unwrap().position = spot;
+        world.ais.get_mut(enemy).unwrap().post = spot;
+
+        let player = world.player;
+        world.transforms.get_mut(player).unwrap().position = spot + Vec2::new(gap * TILE_SIZE, 0.0);
+        remember_positions(&mut world);
+        snap_camera(&mut world);
+
+        (world, enemy)
+    }
+
+    #[test]
+    fn projectiles_hit_enemies_anywhere_on_the_map() {
+        // the corners of level1 inside its walls, its middle and along its edges
+        let spots = [(2, 2), (76, 2), (2, 76), (76, 76), (40, 40), (40, 76), (76, 40)];
+        let first_screen = Vec2::new(640.0, 480.0);
+
+        for &(x, y) in spots.iter() {
+            // stand on the side towards the middle and shoot at the enemy
+            let toward_enemy: f32 = if x < 40 { -1.0 } else { 1.0 };
+            let (mut world, enemy) = level1_with_enemy_at(x, y, -4.0 * toward_enemy);
+            let start = health(&world, enemy);
+
+            if (x, y) != (2, 2) {
+                assert!(world.camera.position.distance(first_screen) > 640.0, "({}, {})", x, y);
+            }
+            assert!(world.is_visible(enemy), "({}, {})", x, y);
+
+            world.update(&Input {
+                movement: Vec2::new(toward_enemy, 0.0),
+                ..Input::default()
+            });
+            world.update(&Input {
+                attack: true,
+                ..Input::default()
+            });
+            assert!(world.projectiles.iter().next().is_some(), "({}, {})", x, y);
+
+            run(&mut world, 30);
+
+            assert!(health(&world, enemy) < start, "no hit at ({}, {})", x, y);
+            assert!(world.projectiles.iter().next().is_none(), "({}, {})", x, y);
+        }
+    }
+
+    // enough ticks for the player's second of invulnerability after a hit to run out
+    const INVULNERABLE_TICKS: usize = 65;
+    // and for its two second respawn delay
+    const RESPAWN_TICKS: usize = 125;
+
+    fn kill(world: &mut World, entity: Entity) {
+        let from = center(world, entity).unwrap();
+        damage(world, entity, 100, PHYSICAL, Some(from));
+    }
+
+    #[test]
+    fn invulnerability_blocks_a_second_hit() {
+        let mut world = open_world(20, &[("player", 5, 5)]);
+        let player = world.player;
+        let from = position(&world, player);
+
+        damage(&mut world, player, 1, PHYSICAL, Some(from));
+        assert_eq!(health(&world, player), 4);
+
+        damage(&mut world, player, 1, PHYSICAL, Some(from));
+        assert_eq!(health(&world, player), 4);
+
+        run(&mut world, INVULNERABLE_TICKS);
+        damage(&mut world, player, 1, PHYSICAL, Some(from));
+        assert_eq!(health(&world, player), 3);
+    }
+
+    #[test]
+    fn hits_knock_away_from_where_they_came_from() {
+        let mut world = open_world(20, &[("player", 5, 5)]);
+        let player = world.player;
+        let start = position(&world, player);
+
+        // from the left, so the player goes right
+        damage(&mut world, player, 1, PHYSICAL, Some(start + Vec2::new(-10.0, 24.0)));
+        assert!(world.velocities.get(player).unwrap().0.x > 0.0);
+
+        run(&mut world, 5);
+        let pushed = position(&world, player);
+        assert!(pushed.x > start.x);
+        assert_eq!(pushed.y, start.y);
+
+        // the push wears off and the player comes to a stop
+        run(&mut world, 60);
+        let stopped = position(&world, player);
+        run(&mut world, 10);
+        assert_eq!(position(&world, player), stopped);
+    }
+
+    #[test]
+    fn the_player_respawns_at_the_last_checkpoint() {
+        let mut world = open_world(20, &[("player", 5, 5), ("checkpoint", 10, 5)]);
+        let player = world.player;
+        let checkpoint = find(&world, "checkpoint");
+        let walk = |x: f32| Input {
+            movement: Vec2::new(x, 0.0),
+            ..Input::default()
+        };
+
+        for _ in 0..120 {
+            if world.checkpoints.get(checkpoint).unwrap().reached {
+                break;
+            }
+            world.update(&walk(1.0));
+        }
+        assert!(world.checkpoints.get(checkpoint).unwrap().reached);
+
+        // wander off again so respawning visibly moves the player
+        for _ in 0..60 {
+            world.update(&walk(-1.0));
+        }
+
+        kill(&mut world, player);
+        assert!(!world.player_alive());
+        assert!(!world.game_over());
+        assert_eq!(world.respawns.get(player).unwrap().lives, Some(2));
+
+        run(&mut world, RESPAWN_TICKS);
+
+        // centred on the checkpoint, which is 128x32 at tile (10, 5)
+        let checkpoint_center = Vec2::new(10.0 * TILE_SIZE + 64.0, 5.0 * TILE_SIZE + 16.0);
+        assert!(world.player_alive());
+        assert_eq!(health(&world, player), 5);
+        assert_eq!(position(&world, player), checkpoint_center - Vec2::new(24.0, 24.0));
+    }
+
+    #[test]
+    fn the_game_is_over_once_the_lives_run_out() {
+        let mut world = open_world(20, &[("player", 5, 5)]);
+        let player = world.player;
+        let start = position(&world, player);
+
+        // three lives, so three times back at the start
+        for lives in (0..3).rev() {
+            kill(&mut world, player);
+            assert_eq!(world.respawns.get(player).unwrap().lives, Some(lives));
+            assert!(!world.game_over());
+
+            run(&mut world, RESPAWN_TICKS + INVULNERABLE_TICKS);
+            assert!(world.player_alive());
+            assert_eq!(position(&world, player), start);
+        }
+
+        kill(&mut world, player);
+        assert!(world.game_over());
+
+        run(&mut world, RESPAWN_TICKS * 2);
+        assert!(world.game_over());
+        assert_eq!(health(&world, player), 0);
+    }
+
+    #[test]
+    fn enemies_resist_physical_damage() {
+        let mut world = open_world(20, &[("player", 2, 2), ("enemy", 10, 10)]);
+        let enemy = find(&world, "enemy");
+        let from = position(&world, enemy);
+
+        // half of 2 physical gets through
+        damage(&mut world, enemy, 2, PHYSICAL, Some(from));
+        assert_eq!(health(&world, enemy), 2);
+
+        // the player's arcane attacks aren't resisted
+        run(&mut world, 20);
+        damage(&mut world, enemy, 1, "arcane", Some(from));
+        assert_eq!(health(&world, enemy), 1);
+    }
+
+    // the game's config with one more floor tile, id 9, that hurts or slides
+    fn config_with_floor(floor: &str) -> WorldConfig {
+        let mut config = WorldConfig::load("./resources", 1280.0, 960.0).unwrap();
+        let source = format!(
+            r#"{{ "tiles": [{{ "id": 4, "name": "grass", "texture": "grass.png" }}, {{ "id": 9, "name": "floor", "texture": "floor.png", {} }}] }}"#,
+            floor
+        );
+        config.tile_registry = TileRegistry::parse(&source).unwrap();
+
+        config
+    }
+
+    #[test]
+    fn damaging_tiles_hurt_what_stands_on_them() {
+        let mut world = floor_world(config_with_floor(r#""damage": 1"#), 9, 20, &[("player", 5, 5)]);
+        let player = world.player;
+        let start = position(&world, player);
+
+        run(&mut world, 1);
+        assert_eq!(health(&world, player), 4);
+
+        // without being pushed anywhere, and again once invulnerability wears off
+        assert_eq!(position(&world, player), start);
+        run(&mut world, INVULNERABLE_TICKS);
+        assert_eq!(health(&world, player), 3);
+    }
+
+    #[test]
+    fn the_player_can_walk_off_a_damaging_tile() {
+        let config = config_with_floor(r#""damage": 1"#);
+
+        // a strip of hazard on the left, grass from column 8 on
+        let mut source = String::from("size 20 20\nspawn player 160 160\nlayer ground\n");
+        for _ in 0..20 {
+            source += &format!("{} {}\n", ["9"; 8].join(" "), ["4"; 12].join(" "));
+        }
+        source += "end\n";
+        let level = Level::parse(&source, &config.tile_registry).unwrap();
+        let mut world = World::new(&level, config).unwrap();
+        let player = world.player;
+        let walk = Input {
+            movement: Vec2::new(1.0, 0.0),
+            ..Input::default()
+        };
+
+        world.update(&walk);
+        assert_eq!(health(&world, player), 4);
+
+        // hurt but still in control, it keeps speeding up
+        let mut speed = world.velocities.get(player).unwrap().0.x;
+        assert!(speed > 0.0);
+        for _ in 0..3 {
+            world.update(&walk);
+            let faster = world.velocities.get(player).unwrap().0.x;
+            assert!(faster > speed);
+            speed = faster;
+        }
+        assert_eq!(world.hurt_responses.get(player).unwrap().knocked_back, Duration::from_secs(0));
+
+        // and is off the hazard long before the invulnerability runs out
+        for _ in 0..30 {
+            world.update(&walk);
+        }
+        assert!(position(&world, player).x >= 8.0 * TILE_SIZE);
+        run(&mut world, INVULNERABLE_TICKS);
+        assert_eq!(health(&world, player), 4);
+    }
+
+    #[test]
+    fn slippery_tiles_slow_speeding_up_and_stopping() {
+        let mut normal = open_world(20, &[("player", 5, 5)]);
+        let mut ice = floor_world(config_with_floor(r#""friction": 0.25"#), 9, 20, &[("player", 5, 5)]);
+        let walk = Input {
+            movement: Vec2::new(1.0, 0.0),
+            ..Input::default()
+        };
+        let speed = |world: &World| world.velocities.get(world.player).unwrap().0.x;
+
+        normal.update(&walk);
+        ice.update(&walk);
+        assert!(speed(&ice) > 0.0);
+        assert!((speed(&ice) - speed(&normal) * 0.25).abs() < 0.01);
+
+        for _ in 0..30 {
+            normal.update(&walk);
+            ice.update(&walk);
+        }
+        assert_eq!(speed(&ice), speed(&normal));
+
+        run(&mut normal, 1);
+        run(&mut ice, 1);
+        assert!(speed(&ice) > speed(&normal));
+    }
+}
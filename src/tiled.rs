@@ -0,0 +1,862 @@
+// Importer for maps made in the Tiled editor (https://www.mapeditor.org).
+//
+// Only orthogonal, finite maps with 32x32 tiles are supported. Tile layers can be
+// CSV or base64 encoded (optionally zlib or gzip compressed), tilesets can be
+// embedded or external (.tsx / .tsj) and object layers provide the spawn points.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde_json::Value;
+use vek::Vec2;
+
+use crate::error::Error;
+use crate::level::{Level, Spawn, TileLayer, MAX_LEVEL_SIZE, TILE_SIZE};
+use crate::tiles::TileRegistry;
+
+// Tiled stores flip/rotation flags in the top bits of every gid.
+const GID_FLAG_MASK: u32 = 0xF000_0000;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Xml(String),
+    Json(String),
+    Unsupported(String),
+    InvalidSize { width: usize, height: usize },
+    InvalidData { layer: String, message: String },
+    UnknownGid { layer: String, x: usize, y: usize, gid: u32 },
+    UnknownTile { layer: String, x: usize, y: usize, id: i32 },
+    MissingAttribute { element: String, attribute: String },
+    MissingPlayerSpawn,
+    DuplicatePlayerSpawn,
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TiledError::Xml(message) => write!(f, "invalid XML: {}", message),
+            TiledError::Json(message) => write!(f, "invalid JSON: {}", message),
+            TiledError::Unsupported(message) => write!(f, "unsupported map: {}", message),
+            TiledError::InvalidSize { width, height } => write!(
+                f,
+                "size {}x{} is out of range (1 to {} tiles per side)",
+                width, height, MAX_LEVEL_SIZE
+            ),
+            TiledError::InvalidData { layer, message } => {
+                write!(f, "layer `{}`: {}", layer, message)
+            }
+            TiledError::UnknownGid { layer, x, y, gid } => write!(
+                f,
+                "layer `{}` at ({}, {}): gid {} is not in any tileset",
+                layer, x, y, gid
+            ),
+            TiledError::UnknownTile { layer, x, y, id } => write!(
+                f,
+                "layer `{}` at ({}, {}): unknown tile id {}",
+                layer, x, y, id
+            ),
+            TiledError::MissingAttribute { element, attribute } => {
+                write!(f, "<{}> is missing the `{}` attribute", element, attribute)
+            }
+            TiledError::MissingPlayerSpawn => write!(f, "map has no object of type `player`"),
+            TiledError::DuplicatePlayerSpawn => write!(f, "map has more than one object of type `player`"),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+// What a single tileset tile turns into once imported.
+#[derive(Default)]
+struct TiledTile {
+    id: Option<i32>,
+    collidable: Option<bool>,
+}
+
+struct Tileset {
+    first_gid: u32,
+    tiles: HashMap<u32, TiledTile>,
+}
+
+struct ObjectSpawn {
+    kind: String,
+    position: Vec2<f32>,
+}
+
+// The parts of a map shared by the .tmx and .tmj formats.
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tilesets: Vec<Tileset>,
+    layers: Vec<(String, Vec<u32>)>,
+    objects: Vec<ObjectSpawn>,
+}
+
+pub fn load(path: &Path, registry: &TileRegistry) -> crate::Result<Level> {
+    let source = read_file(path)?;
+
+    let map = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") => parse_tmx(&source, path),
+        _ => parse_tmj(&source, path),
+    };
+
+    let mut level = map.and_then(|map| map.into_level(registry)).map_err(|error| match error {
+        LoadError::Io(error) => error,
+        LoadError::Tiled(error) => invalid_asset(path, error),
+    })?;
+
+    level.name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(level)
+}
+
+enum LoadError {
+    Io(Error),
+    Tiled(TiledError),
+}
+
+impl From<TiledError> for LoadError {
+    fn from(error: TiledError) -> LoadError {
+        LoadError::Tiled(error)
+    }
+}
+
+impl From<Error> for LoadError {
+    fn from(error: Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+fn read_file(path: &Path) -> crate::Result<String> {
+    fs::read_to_string(path).map_err(|reason| Error::FailedToLoadAsset {
+        reason,
+        path: path.to_path_buf(),
+    })
+}
+
+fn invalid_asset(path: &Path, error: TiledError) -> Error {
+    Error::FailedToLoadAsset {
+        reason: io::Error::new(io::ErrorKind::InvalidData, error),
+        path: path.to_path_buf(),
+    }
+}
+
+// External tilesets are resolved relative to the file that references them.
+fn sibling_path(path: &Path, source: &str) -> PathBuf {
+    match path.parent() {
+        Some(parent) => parent.join(source),
+        None => PathBuf::from(source),
+    }
+}
+
+impl TiledMap {
+    fn into_level(self, registry: &TileRegistry) -> Result<Level, LoadError> {
+        let mut collidable_overrides: HashMap<i32, bool> = HashMap::new();
+        let mut layers: Vec<TileLayer> = Vec::new();
+
+        for (name, gids) in self.layers {
+            if gids.len() != self.width * self.height {
+                return Err(TiledError::InvalidData {
+                    layer: name,
+                    message: format!("expected {} tiles, found {}", self.width * self.height, gids.len()),
+                }
+                .into());
+            }
+
+            let mut tiles: Vec<i32> = Vec::with_capacity(gids.len());
+
+            for (index, raw_gid) in gids.iter().enumerate() {
+                let gid = raw_gid & !GID_FLAG_MASK;
+                let x = index % self.width;
+                let y = index / self.width;
+
+                if gid == 0 {
+                    tiles.push(0);
+                    continue;
+                }
+
+                let tileset = self
+                    .tilesets
+                    .iter()
+                    .filter(|tileset| tileset.first_gid <= gid)
+                    .max_by_key(|tileset| tileset.first_gid)
+                    .ok_or_else(|| TiledError::UnknownGid { layer: name.clone(), x, y, gid })?;
+
+                // Without an explicit `id` property the gid is used, so the first tile of
+                // the first tileset is id 1 (stone), the second is 2 and so on, and later
+                // tilesets carry on from there instead of starting again at 1.
+                let local_id = gid - tileset.first_gid;
+                let tile = tileset.tiles.get(&local_id);
+                let id = tile.and_then(|tile| tile.id).unwrap_or(gid as i32);
+
+                if !registry.is_known(id) {
+                    return Err(TiledError::UnknownTile { layer: name, x, y, id }.into());
+                }
+
+                if let Some(collidable) = tile.and_then(|tile| tile.collidable) {
+                    collidable_overrides.insert(id, collidable);
+                }
+
+                tiles.push(id);
+            }
+
+            layers.push(TileLayer { name, tiles });
+        }
+
+        let mut player_spawn: Option<Vec2<f32>> = None;
+        let mut spawns: Vec<Spawn> = Vec::new();
+
+        for object in self.objects {
+            match object.kind.as_str() {
+                "player" if player_spawn.is_some() => return Err(TiledError::DuplicatePlayerSpawn.into()),
+                "player" => player_spawn = Some(object.position),
+                // plain objects without a type are left for the map's own use
+                "" => {}
+                _ => spawns.push(Spawn {
+                    prefab: object.kind,
+                    position: object.position,
+                }),
+            }
+        }
+
+        Ok(Level {
+            name: String::new(),
+            width: self.width,
+            height: self.height,
+            layers,
+            player_spawn: player_spawn.ok_or(TiledError::MissingPlayerSpawn)?,
+            spawns,
+            collidable_overrides,
+        })
+    }
+}
+
+fn check_map_header(orientation: &str, infinite: bool, tile_width: f32, tile_height: f32, width: usize, height: usize) -> Result<(), TiledError> {
+    if orientation != "orthogonal" {
+        return Err(TiledError::Unsupported(format!("{} orientation", orientation)));
+    }
+
+    if infinite {
+        return Err(TiledError::Unsupported(String::from("infinite maps")));
+    }
+
+    if (tile_width - TILE_SIZE).abs() > f32::EPSILON || (tile_height - TILE_SIZE).abs() > f32::EPSILON {
+        return Err(TiledError::Unsupported(format!(
+            "{}x{} tiles, expected {}x{}",
+            tile_width, tile_height, TILE_SIZE, TILE_SIZE
+        )));
+    }
+
+    if width == 0 || height == 0 || width > MAX_LEVEL_SIZE || height > MAX_LEVEL_SIZE {
+        return Err(TiledError::InvalidSize { width, height });
+    }
+
+    Ok(())
+}
+
+fn decode_csv(layer: &str, data: &str) -> Result<Vec<u32>, TiledError> {
+    data.split(',')
+        .map(|cell| cell.trim())
+        .filter(|cell| !cell.is_empty())
+        .map(|cell| {
+            cell.parse().map_err(|_| TiledError::InvalidData {
+                layer: layer.to_string(),
+                message: format!("`{}` is not a gid", cell),
+            })
+        })
+        .collect()
+}
+
+// unencoded .tmx data, one <tile> per cell, a missing gid being an empty cell
+fn decode_xml_tiles(layer: &str, data: roxmltree::Node) -> Result<Vec<u32>, TiledError> {
+    data.children()
+        .filter(|tile| tile.has_tag_name("tile"))
+        .map(|tile| match tile.attribute("gid") {
+            None => Ok(0),
+            Some(gid) => gid.parse().map_err(|_| TiledError::InvalidData {
+                layer: layer.to_string(),
+                message: format!("`{}` is not a gid", gid),
+            }),
+        })
+        .collect()
+}
+
+fn decode_json_array(layer: &str, cells: &[Value]) -> Result<Vec<u32>, TiledError> {
+    cells
+        .iter()
+        .map(|cell| {
+            cell.as_u64().and_then(|gid| u32::try_from(gid).ok()).ok_or_else(|| TiledError::InvalidData {
+                layer: layer.to_string(),
+                message: format!("`{}` is not a gid", cell),
+            })
+        })
+        .collect()
+}
+
+fn decode_base64(layer: &str, data: &str, compression: Option<&str>) -> Result<Vec<u32>, TiledError> {
+    let invalid = |message: String| TiledError::InvalidData {
+        layer: layer.to_string(),
+        message,
+    };
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|error| invalid(format!("bad base64 data: {}", error)))?;
+
+    let mut decoded: Vec<u8> = Vec::new();
+
+    match compression {
+        None | Some("") => decoded = bytes,
+        Some("zlib") => {
+            ZlibDecoder::new(&bytes[..])
+                .read_to_end(&mut decoded)
+                .map_err(|error| invalid(format!("bad zlib data: {}", error)))?;
+        }
+        Some("gzip") => {
+            GzDecoder::new(&bytes[..])
+                .read_to_end(&mut decoded)
+                .map_err(|error| invalid(format!("bad gzip data: {}", error)))?;
+        }
+        Some(other) => return Err(TiledError::Unsupported(format!("{} compression", other))),
+    }
+
+    if !decoded.len().is_multiple_of(4) {
+        return Err(invalid(format!("{} bytes is not a whole number of gids", decoded.len())));
+    }
+
+    Ok(decoded
+        .chunks(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+fn apply_property(tile: &mut TiledTile, name: &str, value: &str) {
+    match name {
+        "id" => tile.id = value.parse().ok(),
+        "collidable" => tile.collidable = Some(value == "true"),
+        _ => {}
+    }
+}
+
+// .tmx / .tsx
+
+fn attribute<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str, TiledError> {
+    node.attribute(name).ok_or_else(|| TiledError::MissingAttribute {
+        element: node.tag_name().name().to_string(),
+        attribute: name.to_string(),
+    })
+}
+
+fn number_attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, TiledError> {
+    let value = attribute(node, name)?;
+
+    value.parse().map_err(|_| {
+        TiledError::Xml(format!(
+            "<{}> attribute `{}` is not a number: `{}`",
+            node.tag_name().name(),
+            name,
+            value
+        ))
+    })
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn parse_tmx(source: &str, path: &Path) -> Result<TiledMap, LoadError> {
+    let document = roxmltree::Document::parse(source).map_err(|error| TiledError::Xml(error.to_string()))?;
+    let map = document.root_element();
+
+    let width: usize = number_attribute(map, "width")?;
+    let height: usize = number_attribute(map, "height")?;
+
+    check_map_header(
+        map.attribute("orientation").unwrap_or("orthogonal"),
+        map.attribute("infinite") == Some("1"),
+        number_attribute(map, "tilewidth")?,
+        number_attribute(map, "tileheight")?,
+        width,
+        height,
+    )?;
+
+    let mut tilesets: Vec<Tileset> = Vec::new();
+    let mut layers: Vec<(String, Vec<u32>)> = Vec::new();
+    let mut objects: Vec<ObjectSpawn> = Vec::new();
+
+    for node in map.children().filter(|node| node.is_element()) {
+        match node.tag_name().name() {
+            "tileset" => {
+                let first_gid: u32 = number_attribute(node, "firstgid")?;
+
+                let tiles = match node.attribute("source") {
+                    Some(source) => load_external_tileset(&sibling_path(path, source))?,
+                    None => parse_tsx_tiles(node)?,
+                };
+
+                tilesets.push(Tileset { first_gid, tiles });
+            }
+            "layer" => {
+                let name = node.attribute("name").unwrap_or("").to_string();
+                let data = child(node, "data").ok_or_else(|| TiledError::InvalidData {
+                    layer: name.clone(),
+                    message: String::from("layer has no <data>"),
+                })?;
+                let text = data.text().unwrap_or("");
+
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => decode_csv(&name, text)?,
+                    Some("base64") => decode_base64(&name, text, data.attribute("compression"))?,
+                    Some(other) => return Err(TiledError::Unsupported(format!("{} encoding", other)).into()),
+                    None => decode_xml_tiles(&name, data)?,
+                };
+
+                layers.push((name, gids));
+            }
+            "objectgroup" => {
+                for object in node.children().filter(|object| object.has_tag_name("object")) {
+                    // Tiled 1.9 renamed `type` to `class`
+                    let kind = object.attribute("type").or_else(|| object.attribute("class")).unwrap_or("");
+                    let mut position = Vec2::new(number_attribute(object, "x")?, number_attribute(object, "y")?);
+
+                    // tile objects are placed by their bottom left corner
+                    if object.has_attribute("gid") {
+                        position.y -= number_attribute::<f32>(object, "height")?;
+                    }
+
+                    objects.push(ObjectSpawn {
+                        kind: kind.to_string(),
+                        position,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(TiledMap { width, height, tilesets, layers, objects })
+}
+
+fn parse_tsx_tiles(tileset: roxmltree::Node) -> Result<HashMap<u32, TiledTile>, TiledError> {
+    let mut tiles: HashMap<u32, TiledTile> = HashMap::new();
+
+    for node in tileset.children().filter(|node| node.has_tag_name("tile")) {
+        let mut tile = TiledTile::default();
+
+        if let Some(properties) = child(node, "properties") {
+            for property in properties.children().filter(|property| property.has_tag_name("property")) {
+                apply_property(&mut tile, attribute(property, "name")?, property.attribute("value").unwrap_or(""));
+            }
+        }
+
+        tiles.insert(number_attribute(node, "id")?, tile);
+    }
+
+    Ok(tiles)
+}
+
+fn load_external_tileset(path: &Path) -> Result<HashMap<u32, TiledTile>, LoadError> {
+    let source = read_file(path)?;
+
+    let tiles = if path.extension().and_then(|ext| ext.to_str()) == Some("tsx") {
+        roxmltree::Document::parse(&source)
+            .map_err(|error| TiledError::Xml(error.to_string()))
+            .and_then(|document| parse_tsx_tiles(document.root_element()))
+    } else {
+        serde_json::from_str(&source)
+            .map_err(|error| TiledError::Json(error.to_string()))
+            .and_then(|tileset: Value| parse_tsj_tiles(&tileset))
+    };
+
+    // errors inside an external tileset are reported against the tileset's own path
+    tiles.map_err(|error| LoadError::Io(invalid_asset(path, error)))
+}
+
+// .tmj / .tsj
+
+fn json_number(value: &Value, key: &str) -> Result<f64, TiledError> {
+    value[key]
+        .as_f64()
+        .ok_or_else(|| TiledError::Json(format!("missing number `{}`", key)))
+}
+
+fn parse_tmj(source: &str, path: &Path) -> Result<TiledMap, LoadError> {
+    let map: Value = serde_json::from_str(source).map_err(|error| TiledError::Json(error.to_string()))?;
+
+    let width = json_number(&map, "width")? as usize;
+    let height = json_number(&map, "height")? as usize;
+
+    check_map_header(
+        map["orientation"].as_str().unwrap_or("orthogonal"),
+        map["infinite"].as_bool().unwrap_or(false),
+        json_number(&map, "tilewidth")? as f32,
+        json_number(&map, "tileheight")? as f32,
+        width,
+        height,
+    )?;
+
+    let mut tilesets: Vec<Tileset> = Vec::new();
+    let mut layers: Vec<(String, Vec<u32>)> = Vec::new();
+    let mut objects: Vec<ObjectSpawn> = Vec::new();
+
+    for tileset in map["tilesets"].as_array().into_iter().flatten() {
+        let first_gid = json_number(tileset, "firstgid")? as u32;
+
+        let tiles = match tileset["source"].as_str() {
+            Some(source) => load_external_tileset(&sibling_path(path, source))?,
+            None => parse_tsj_tiles(tileset)?,
+        };
+
+        tilesets.push(Tileset { first_gid, tiles });
+    }
+
+    for layer in map["layers"].as_array().into_iter().flatten() {
+        let name = layer["name"].as_str().unwrap_or("").to_string();
+
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let gids = match &layer["data"] {
+                    Value::Array(cells) => decode_json_array(&name, cells)?,
+                    Value::String(data) => decode_base64(&name, data, layer["compression"].as_str())?,
+                    _ => {
+                        return Err(TiledError::InvalidData {
+                            layer: name,
+                            message: String::from("layer has no data"),
+                        }
+                        .into())
+                    }
+                };
+
+                layers.push((name, gids));
+            }
+            Some("objectgroup") => {
+                for object in layer["objects"].as_array().into_iter().flatten() {
+                    let kind = object["type"].as_str().or_else(|| object["class"].as_str()).unwrap_or("");
+                    let mut position = Vec2::new(json_number(object, "x")? as f32, json_number(object, "y")? as f32);
+
+                    // tile objects are placed by their bottom left corner
+                    if object.get("gid").is_some() {
+                        position.y -= json_number(object, "height")? as f32;
+                    }
+
+                    objects.push(ObjectSpawn {
+                        kind: kind.to_string(),
+                        position,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(TiledMap { width, height, tilesets, layers, objects })
+}
+
+fn parse_tsj_tiles(tileset: &Value) -> Result<HashMap<u32, TiledTile>, TiledError> {
+    let mut tiles: HashMap<u32, TiledTile> = HashMap::new();
+
+    for node in tileset["tiles"].as_array().into_iter().flatten() {
+        let mut tile = TiledTile::default();
+
+        for property in node["properties"].as_array().into_iter().flatten() {
+            let value = match &property["value"] {
+                Value::String(value) => value.clone(),
+                other => other.to_string(),
+            };
+
+            apply_property(&mut tile, property["name"].as_str().unwrap_or(""), &value);
+        }
+
+        tiles.insert(json_number(node, "id")? as u32, tile);
+    }
+
+    Ok(tiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer_error(map: Result<TiledMap, LoadError>) -> (String, String) {
+        match map {
+            Err(LoadError::Tiled(TiledError::InvalidData { layer, message })) => (layer, message),
+            Err(LoadError::Tiled(error)) => panic!("wrong error: {}", error),
+            Err(LoadError::Io(error)) => panic!("wrong error: {}", error),
+            Ok(_) => panic!("a malformed gid was accepted"),
+        }
+    }
+
+    #[test]
+    fn unencoded_tmx_data_rejects_bad_gids() {
+        let source = r#"<map width="2" height="1" tilewidth="32" tileheight="32">
+            <layer name="ground"><data><tile gid="1"/><tile gid="grass"/></data></layer>
+        </map>"#;
+
+        let (layer, message) = layer_error(parse_tmx(source, Path::new("map.tmx")));
+        assert_eq!(layer, "ground");
+        assert!(message.contains("grass"));
+
+        // a <tile> without a gid is an empty cell
+        let source = r#"<map width="2" height="1" tilewidth="32" tileheight="32">
+            <layer name="ground"><data><tile gid="1"/><tile/></data></layer>
+        </map>"#;
+        let map = parse_tmx(source, Path::new("map.tmx")).ok().unwrap();
+        assert_eq!(map.layers[0].1, vec![1, 0]);
+    }
+
+    #[test]
+    fn tmj_array_data_rejects_bad_gids() {
+        for cell in ["\"1\"", "-1", "1.5", "4294967296"].iter() {
+            let source = format!(
+                r#"{{"width": 2, "height": 1, "tilewidth": 32, "tileheight": 32,
+                    "layers": [{{"name": "walls", "type": "tilelayer", "data": [1, {}]}}]}}"#,
+                cell
+            );
+
+            let (layer, message) = layer_error(parse_tmj(&source, Path::new("map.tmj")));
+            assert_eq!(layer, "walls");
+            assert!(message.contains(cell), "{}", message);
+        }
+    }
+
+    fn registry() -> TileRegistry {
+        let source = r#"{ "tiles": [
+            { "id": 1, "name": "stone", "texture": "stone.png", "collidable": true },
+            { "id": 2, "name": "woodplank", "texture": "woodplank.png" },
+            { "id": 3, "name": "grass", "texture": "grass.png" },
+            { "id": 4, "name": "water", "texture": "water.png", "collidable": true },
+            { "id": 7, "name": "sand", "texture": "sand.png" }
+        ] }"#;
+
+        TileRegistry::parse(source).unwrap()
+    }
+
+    // Writes `files` into a directory of their own and loads the first one as a map.
+    fn load_files(test: &str, files: &[(&str, &str)]) -> crate::Result<Level> {
+        let directory = std::env::temp_dir().join(format!("hypoterra-tiled-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        for (name, source) in files {
+            fs::write(directory.join(name), source).unwrap();
+        }
+
+        let level = load(&directory.join(files[0].0), &registry());
+        fs::remove_dir_all(&directory).unwrap();
+
+        level
+    }
+
+    fn tmx(tilesets: &str, layers: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <map version="1.10" orientation="orthogonal" width="2" height="2" tilewidth="32" tileheight="32" infinite="0">
+                {}
+                {}
+                <objectgroup name="spawns"><object id="1" type="player" x="40" y="8"/></objectgroup>
+            </map>"#,
+            tilesets, layers
+        )
+    }
+
+    fn tmj(tilesets: &str, layers: &str) -> String {
+        format!(
+            r#"{{"width": 2, "height": 2, "tilewidth": 32, "tileheight": 32, "orientation": "orthogonal", "infinite": false,
+                "tilesets": [{}],
+                "layers": [{}, {{"name": "spawns", "type": "objectgroup", "objects": [{{"type": "player", "x": 40, "y": 8}}]}}]}}"#,
+            tilesets, layers
+        )
+    }
+
+    const TSX_TILESET: &str = r#"<tileset firstgid="1" name="ground" tilewidth="32" tileheight="32" tilecount="4" columns="4"/>"#;
+    const TSJ_TILESET: &str = r#"{"firstgid": 1, "name": "ground", "tilecount": 4}"#;
+
+    // the same 2x2 layer, [1, 2, 3, 0], in every encoding
+    const GIDS: [u32; 4] = [1, 2, 3, 0];
+
+    fn base64_gids(compression: Option<&str>) -> String {
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let bytes: Vec<u8> = GIDS.iter().flat_map(|gid| gid.to_le_bytes().to_vec()).collect();
+
+        let compressed = match compression {
+            None => bytes,
+            Some("zlib") => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+            Some(_) => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes).unwrap();
+                encoder.finish().unwrap()
+            }
+        };
+
+        base64::engine::general_purpose::STANDARD.encode(compressed)
+    }
+
+    #[test]
+    fn csv_layers() {
+        let layers = r#"<layer name="ground" width="2" height="2"><data encoding="csv">
+1,2,
+3,0
+</data></layer>"#;
+        let level = load_files("csv", &[("map.tmx", &tmx(TSX_TILESET, layers))]).unwrap();
+
+        assert_eq!(level.name, "map");
+        assert_eq!((level.width, level.height), (2, 2));
+        assert_eq!(level.layers.len(), 1);
+        assert_eq!(level.layers[0].name, "ground");
+        assert_eq!(level.layers[0].tiles, vec![1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn base64_layers() {
+        for compression in [None, Some("zlib"), Some("gzip")].iter() {
+            let data = base64_gids(*compression);
+
+            let attribute = compression.map_or(String::new(), |compression| format!(r#" compression="{}""#, compression));
+            let layers = format!(r#"<layer name="ground"><data encoding="base64"{}>{}</data></layer>"#, attribute, data);
+            let level = load_files("base64-tmx", &[("map.tmx", &tmx(TSX_TILESET, &layers))]).unwrap();
+            assert_eq!(level.layers[0].tiles, vec![1, 2, 3, 0], "{:?}", compression);
+
+            let field = compression.map_or(String::new(), |compression| format!(r#", "compression": "{}""#, compression));
+            let layers = format!(r#"{{"name": "ground", "type": "tilelayer", "encoding": "base64", "data": "{}"{}}}"#, data, field);
+            let level = load_files("base64-tmj", &[("map.tmj", &tmj(TSJ_TILESET, &layers))]).unwrap();
+            assert_eq!(level.layers[0].tiles, vec![1, 2, 3, 0], "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn external_tilesets() {
+        let tsx = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <tileset name="ground" tilewidth="32" tileheight="32" tilecount="4" columns="4">
+                <tile id="2"><properties><property name="id" type="int" value="7"/></properties></tile>
+            </tileset>"#;
+        let tsj = r#"{"name": "ground", "tilecount": 4, "tiles": [
+            {"id": 2, "properties": [{"name": "id", "type": "int", "value": 7}]}
+        ]}"#;
+        let tmx_layers = r#"<layer name="ground"><data encoding="csv">1,2,3,0</data></layer>"#;
+        let tmj_layers = r#"{"name": "ground", "type": "tilelayer", "data": [1, 2, 3, 0]}"#;
+
+        let level = load_files(
+            "tsx",
+            &[("map.tmx", &tmx(r#"<tileset firstgid="1" source="ground.tsx"/>"#, tmx_layers)), ("ground.tsx", tsx)],
+        )
+        .unwrap();
+        assert_eq!(level.layers[0].tiles, vec![1, 2, 7, 0]);
+
+        let level = load_files(
+            "tsj",
+            &[("map.tmj", &tmj(r#"{"firstgid": 1, "source": "ground.tsj"}"#, tmj_layers)), ("ground.tsj", tsj)],
+        )
+        .unwrap();
+        assert_eq!(level.layers[0].tiles, vec![1, 2, 7, 0]);
+    }
+
+    #[test]
+    fn object_layers_become_spawns() {
+        let source = r#"{"width": 2, "height": 2, "tilewidth": 32, "tileheight": 32,
+            "tilesets": [{"firstgid": 1, "name": "ground", "tilecount": 4}],
+            "layers": [
+                {"name": "ground", "type": "tilelayer", "data": [1, 2, 3, 0]},
+                {"name": "creatures", "type": "objectgroup", "objects": [
+                    {"type": "enemy", "x": 32, "y": 0},
+                    {"class": "npc", "x": 0, "y": 32},
+                    {"type": "", "x": 16, "y": 16}
+                ]},
+                {"name": "start", "type": "objectgroup", "objects": [{"class": "player", "x": 40.5, "y": 8}]}
+            ]}"#;
+        let level = load_files("objects", &[("map.tmj", source)]).unwrap();
+
+        assert_eq!(level.player_spawn, Vec2::new(40.5, 8.0));
+
+        // untyped objects are skipped
+        let spawns: Vec<(&str, Vec2<f32>)> = level.spawns.iter().map(|spawn| (spawn.prefab.as_str(), spawn.position)).collect();
+        assert_eq!(spawns, vec![("enemy", Vec2::new(32.0, 0.0)), ("npc", Vec2::new(0.0, 32.0))]);
+
+        // and a map without a player doesn't load
+        let source = r#"<map width="1" height="1" tilewidth="32" tileheight="32">
+            <tileset firstgid="1" name="ground" tilewidth="32" tileheight="32" tilecount="4" columns="4"/>
+            <layer name="ground"><data encoding="csv">1</data></layer>
+            <objectgroup><object type="enemy" x="0" y="0"/></objectgroup>
+        </map>"#;
+        let error = parse_tmx(source, Path::new("map.tmx")).and_then(|map| map.into_level(&registry()));
+        assert!(matches!(error, Err(LoadError::Tiled(TiledError::MissingPlayerSpawn))));
+
+        // nor does one with two
+        let source = source.replace(r#"type="enemy""#, r#"type="player""#).replace("</objectgroup>", r#"<object type="player" x="0" y="0"/></objectgroup>"#);
+        let error = parse_tmx(&source, Path::new("map.tmx")).and_then(|map| map.into_level(&registry()));
+        assert!(matches!(error, Err(LoadError::Tiled(TiledError::DuplicatePlayerSpawn))));
+    }
+
+    #[test]
+    fn tile_objects_are_placed_by_their_bottom_left_corner() {
+        let source = r#"<map width="2" height="2" tilewidth="32" tileheight="32">
+            <tileset firstgid="1" name="ground" tilewidth="32" tileheight="32" tilecount="4" columns="4"/>
+            <layer name="ground"><data encoding="csv">1,2,3,0</data></layer>
+            <objectgroup>
+                <object type="player" gid="1" x="32" y="64" width="32" height="32"/>
+                <object type="enemy" x="32" y="64" width="32" height="32"/>
+            </objectgroup>
+        </map>"#;
+        let level = load_files("tile-objects-tmx", &[("map.tmx", source)]).unwrap();
+        assert_eq!(level.player_spawn, Vec2::new(32.0, 32.0));
+        // rectangles keep their top left corner
+        assert_eq!(level.spawns[0].position, Vec2::new(32.0, 64.0));
+
+        let source = r#"{"width": 2, "height": 2, "tilewidth": 32, "tileheight": 32,
+            "tilesets": [{"firstgid": 1, "name": "ground", "tilecount": 4}],
+            "layers": [
+                {"name": "ground", "type": "tilelayer", "data": [1, 2, 3, 0]},
+                {"name": "start", "type": "objectgroup", "objects": [{"type": "player", "gid": 1, "x": 32, "y": 64, "width": 32, "height": 48}]}
+            ]}"#;
+        let level = load_files("tile-objects-tmj", &[("map.tmj", source)]).unwrap();
+        assert_eq!(level.player_spawn, Vec2::new(32.0, 16.0));
+    }
+
+    #[test]
+    fn collidable_property_overrides_the_registry() {
+        let tileset = r#"<tileset firstgid="1" name="ground" tilewidth="32" tileheight="32" tilecount="4" columns="4">
+                <tile id="0"><properties><property name="collidable" type="bool" value="false"/></properties></tile>
+                <tile id="2"><properties><property name="collidable" type="bool" value="true"/></properties></tile>
+            </tileset>"#;
+        let layers = r#"<layer name="ground"><data encoding="csv">1,2,3,0</data></layer>"#;
+        let level = load_files("collidable", &[("map.tmx", &tmx(tileset, layers))]).unwrap();
+
+        // stone is collidable in the registry and grass isn't
+        assert!(!level.collidable(1, true));
+        assert!(level.collidable(3, false));
+        // tiles without the property keep the registry's value
+        assert!(!level.collidable(2, false));
+        assert!(level.collidable(2, true));
+    }
+
+    #[test]
+    fn tiles_of_a_second_tileset_get_their_own_ids() {
+        // two tiles each, so the second tileset starts at gid 3
+        let tilesets = r#"{"firstgid": 1, "name": "ground", "tilecount": 2},
+            {"firstgid": 3, "name": "water", "tilecount": 2}"#;
+        let layers = r#"{"name": "ground", "type": "tilelayer", "data": [1, 2, 3, 4]}"#;
+        let level = load_files("two-tilesets", &[("map.tmj", &tmj(tilesets, layers))]).unwrap();
+
+        // the first tile of each tileset is not the same tile
+        assert_eq!(level.layers[0].tiles, vec![1, 2, 3, 4]);
+
+        // tiles past the end of the registry are still reported
+        let layers = r#"{"name": "ground", "type": "tilelayer", "data": [1, 2, 3, 6]}"#;
+        let error = parse_tmj(&tmj(tilesets, layers), Path::new("map.tmj")).and_then(|map| map.into_level(&registry()));
+        assert!(matches!(error, Err(LoadError::Tiled(TiledError::UnknownTile { x: 1, y: 1, id: 6, .. }))));
+    }
+}
@@ -0,0 +1,224 @@
+use vek::Vec2;
+
+use crate::game_structs::Tile;
+use crate::geometry::Rectangle;
+use crate::level::{Level, TILE_SIZE};
+use crate::tiles::TileRegistry;
+
+// chunks are CHUNK_SIZE x CHUNK_SIZE tiles
+pub const CHUNK_SIZE: usize = 16;
+
+pub fn tiles_from_level(level: &Level, registry: &TileRegistry) -> Vec<Tile> {
+    let mut tiles: Vec<Tile> = Vec::new();
+
+    for layer in 0..level.layers.len() {
+        for y in 0..level.height {
+            for x in 0..level.width {
+                let id = level.tile_at(layer, x, y);
+
+                // ids were checked against the registry when the level was loaded,
+                // so the only id without a definition is the empty tile
+                if let Some(def) = registry.get(id) {
+                    tiles.push(Tile {
+                        id,
+                        position: Vec2::new(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE),
+                        collidable: level.collidable(id, def.collidable),
+                        damage: def.damage,
+                        friction: def.friction,
+                        layer: def.layer,
+                    });
+                }
+            }
+        }
+    }
+
+    // stable, so tiles on the same layer keep the level's layer order
+    tiles.sort_by_key(|tile| tile.layer);
+
+    tiles
+}
+
+// Indices into the tile list, bucketed by which chunk of the map each tile is in.
+pub struct TileChunks {
+    columns: usize,
+    rows: usize,
+    chunks: Vec<Vec<usize>>,
+}
+
+impl TileChunks {
+    pub fn build(tiles: &[Tile], level_width: usize, level_height: usize) -> TileChunks {
+        let columns = level_width.div_ceil(CHUNK_SIZE);
+        let rows = level_height.div_ceil(CHUNK_SIZE);
+        let mut chunks: Vec<Vec<usize>> = vec![Vec::new(); columns * rows];
+        let chunk_pixels = CHUNK_SIZE as f32 * TILE_SIZE;
+
+        for (index, tile) in tiles.iter().enumerate() {
+            let column = ((tile.position.x / chunk_pixels) as usize).min(columns - 1);
+            let row = ((tile.position.y / chunk_pixels) as usize).min(rows - 1);
+
+            chunks[row * columns + column].push(index);
+        }
+
+        TileChunks { columns, rows, chunks }
+    }
+
+    // the chunks overlapping `view`, a rectangle in world coordinates
+    pub fn visible(&self, view: Rectangle) -> impl Iterator<Item = &Vec<usize>> {
+        let chunk_pixels = CHUNK_SIZE as f32 * TILE_SIZE;
+
+        let first_column = (view.x / chunk_pixels).floor().max(0.0) as usize;
+        let first_row = (view.y / chunk_pixels).floor().max(0.0) as usize;
+        let last_column = (((view.x + view.width) / chunk_pixels).ceil().max(0.0) as usize).min(self.columns);
+        let last_row = (((view.y + view.height) / chunk_pixels).ceil().max(0.0) as usize).min(self.rows);
+
+        (first_row..last_row).flat_map(move |row| {
+            (first_column..last_column).map(move |column| &self.chunks[row * self.columns + column])
+        })
+    }
+
+    // how many chunks and tiles a draw of `view` looks at
+    pub fn count_visited(&self, view: Rectangle) -> (usize, usize) {
+        self.visible(view).fold((0, 0), |(chunks, tiles), chunk| (chunks + 1, tiles + chunk.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    use super::*;
+
+    fn level1() -> (Vec<Tile>, Level) {
+        let registry = TileRegistry::load("./resources/tiles.json").unwrap();
+        let level = Level::load("./resources/levels/level1.lvl", &registry).unwrap();
+
+        (tiles_from_level(&level, &registry), level)
+    }
+
+    #[test]
+    fn chunks_visit_fewer_tiles_than_the_whole_map() {
+        let (tiles, level) = level1();
+        let chunks = TileChunks::build(&tiles, level.width, level.height);
+
+        let (width, height) = (1280.0, 960.0);
+        let map_width = level.width as f32 * TILE_SIZE;
+        let map_height = level.height as f32 * TILE_SIZE;
+        let views = [
+            Rectangle::new(0.0, 0.0, width, height),
+            Rectangle::new((map_width - width) / 2.0, (map_height - height) / 2.0, width, height),
+            Rectangle::new(map_width - width, map_height - height, width, height),
+        ];
+
+        for view in views.iter() {
+            let (_, visited) = chunks.count_visited(*view);
+
+            // drawing without chunks looks at every tile
+            assert!(visited < tiles.len() / 2, "{:?} visits {} of {} tiles", view, visited, tiles.len());
+
+            // and nothing on screen is missed
+            let drawn: Vec<usize> = chunks.visible(*view).flatten().copied().collect();
+            let on_screen = tiles.iter().enumerate().filter(|(_, tile)| {
+                tile.position.x < view.x + view.width &&
+                    tile.position.x + TILE_SIZE > view.x &&
+                    tile.position.y < view.y + view.height &&
+                    tile.position.y + TILE_SIZE > view.y
+            });
+            for (index, _) in on_screen {
+                assert!(drawn.contains(&index));
+            }
+        }
+    }
+
+    // a `width` x `height` map with a tile in every cell
+    fn full_map(width: usize, height: usize) -> Vec<Tile> {
+        (0..width * height)
+            .map(|index| Tile {
+                id: 4,
+                position: Vec2::new((index % width) as f32 * TILE_SIZE, (index / width) as f32 * TILE_SIZE),
+                collidable: false,
+                damage: 0,
+                friction: 1.0,
+                layer: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn visited_tiles_depend_on_the_view_not_the_map_size() {
+        let view = Rectangle::new(200.0, 200.0, 1280.0, 960.0);
+        let small = TileChunks::build(&full_map(64, 64), 64, 64);
+        let large = TileChunks::build(&full_map(512, 512), 512, 512);
+
+        assert_eq!(small.count_visited(view), large.count_visited(view));
+    }
+
+    // Times drawing a screen-sized view of a 2048x2048 map with and without chunks. It
+    // takes a while in debug builds, run it with
+    // `cargo test --release --no-default-features --lib tilemap -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_large_map() {
+        let size = 2048;
+        let tiles = full_map(size, size);
+        let chunks = TileChunks::build(&tiles, size, size);
+        let rounds = 100;
+
+        // the camera pans across the map
+        let views: Vec<Rectangle> = (0..rounds)
+            .map(|round| Rectangle::new(round as f32 * 500.0, round as f32 * 300.0, 1280.0, 960.0))
+            .collect();
+
+        let start = Instant::now();
+        let mut visited = 0;
+        for view in &views {
+            visited += black_box(chunks.count_visited(*view)).1;
+        }
+        let chunked = start.elapsed();
+
+        let start = Instant::now();
+        let mut on_screen = 0;
+        for view in &views {
+            on_screen += black_box(&tiles)
+                .iter()
+                .filter(|tile| {
+                    tile.position.x < view.x + view.width &&
+                        tile.position.x + TILE_SIZE > view.x &&
+                        tile.position.y < view.y + view.height &&
+                        tile.position.y + TILE_SIZE > view.y
+                })
+                .count();
+        }
+        let whole = start.elapsed();
+
+        println!(
+            "{} tiles, {} views: chunks visit {} tiles in {:?}, culling every tile finds {} in {:?}",
+            tiles.len(),
+            rounds,
+            visited,
+            chunked,
+            on_screen,
+            whole
+        );
+
+        // a chunk on each side of the screen at most, whatever the size of the map
+        let chunk_tiles = CHUNK_SIZE * CHUNK_SIZE;
+        assert!(visited <= rounds * (1280 / 32 / CHUNK_SIZE + 2) * (960 / 32 / CHUNK_SIZE + 2) * chunk_tiles);
+        assert!(chunked < whole);
+    }
+
+    #[test]
+    fn views_off_the_map_visit_nothing() {
+        let (tiles, level) = level1();
+        let chunks = TileChunks::build(&tiles, level.width, level.height);
+        let map_width = level.width as f32 * TILE_SIZE;
+        let map_height = level.height as f32 * TILE_SIZE;
+
+        assert_eq!(chunks.count_visited(Rectangle::new(-2560.0, -1920.0, 1280.0, 960.0)), (0, 0));
+        assert_eq!(chunks.count_visited(Rectangle::new(map_width + 10.0, 0.0, 1280.0, 960.0)), (0, 0));
+
+        // the whole map visits every tile once
+        let whole = chunks.count_visited(Rectangle::new(0.0, 0.0, map_width, map_height));
+        assert_eq!(whole.1, tiles.len());
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::geometry::Rectangle;
+use crate::util::{asset_path, load_asset};
+
+// id 0 is reserved for "no tile" and never has a definition
+pub const EMPTY_TILE: i32 = 0;
+
+fn default_friction() -> f32 {
+    1.0
+}
+
+fn default_frame_ms() -> u64 {
+    250
+}
+
+#[derive(Deserialize)]
+pub struct TileDef {
+    pub id: i32,
+    pub name: String,
+    pub texture: String,
+    // x, y, width, height inside `texture`, the whole texture is used when missing
+    #[serde(default)]
+    pub region: Option<[f32; 4]>,
+    #[serde(default)]
+    pub collidable: bool,
+    // physical damage dealt every tick to anything standing on the tile
+    #[serde(default)]
+    pub damage: i32,
+    // scales how quickly the player speeds up and slows down on the tile, below 1 is slippery
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+    // regions inside `texture` cycled through every `frame_ms`, replaces `region`
+    #[serde(default)]
+    pub frames: Vec<[f32; 4]>,
+    #[serde(default = "default_frame_ms")]
+    pub frame_ms: u64,
+    // tiles on higher layers are drawn on top
+    #[serde(default)]
+    pub layer: i32,
+}
+
+impl TileDef {
+    // the part of the texture to draw after `elapsed` time, None draws the whole texture
+    pub fn clip(&self, elapsed: Duration) -> Option<Rectangle> {
+        if !self.frames.is_empty() {
+            let frame = (elapsed.as_millis() / u128::from(self.frame_ms.max(1))) as usize % self.frames.len();
+            let [x, y, width, height] = self.frames[frame];
+
+            return Some(Rectangle::new(x, y, width, height));
+        }
+
+        self.region.map(|[x, y, width, height]| Rectangle::new(x, y, width, height))
+    }
+}
+
+#[derive(Deserialize)]
+struct TileFile {
+    tiles: Vec<TileDef>,
+}
+
+#[derive(Debug)]
+pub enum TileRegistryError {
+    Json(String),
+    ReservedId { name: String },
+    DuplicateId { id: i32, first: String, second: String },
+}
+
+impl fmt::Display for TileRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileRegistryError::Json(message) => write!(f, "invalid tile definitions: {}", message),
+            TileRegistryError::ReservedId { name } => write!(
+                f,
+                "tile `{}` uses id {}, which is reserved for empty cells",
+                name, EMPTY_TILE
+            ),
+            TileRegistryError::DuplicateId { id, first, second } => write!(
+                f,
+                "tiles `{}` and `{}` both use id {}",
+                first, second, id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TileRegistryError {}
+
+pub struct TileRegistry {
+    defs: HashMap<i32, TileDef>,
+}
+
+impl TileRegistry {
+    pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<TileRegistry> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        load_asset(path, |source| TileRegistry::parse_in(source, directory))
+    }
+
+    pub fn parse(source: &str) -> Result<TileRegistry, TileRegistryError> {
+        TileRegistry::parse_in(source, Path::new(""))
+    }
+
+    // Like `parse`, with textures relative to `directory`.
+    fn parse_in(source: &str, directory: &Path) -> Result<TileRegistry, TileRegistryError> {
+        let file: TileFile = serde_json::from_str(source).map_err(|error| TileRegistryError::Json(error.to_string()))?;
+        let mut defs: HashMap<i32, TileDef> = HashMap::new();
+
+        for mut def in file.tiles {
+            def.texture = asset_path(directory, &def.texture);
+
+            if def.id == EMPTY_TILE {
+                return Err(TileRegistryError::ReservedId { name: def.name });
+            }
+
+            if let Some(existing) = defs.get(&def.id) {
+                return Err(TileRegistryError::DuplicateId {
+                    id: def.id,
+                    first: existing.name.clone(),
+                    second: def.name,
+                });
+            }
+
+            defs.insert(def.id, def);
+        }
+
+        Ok(TileRegistry { defs })
+    }
+
+    pub fn defs(&self) -> impl Iterator<Item = &TileDef> {
+        self.defs.values()
+    }
+
+    pub fn get(&self, id: i32) -> Option<&TileDef> {
+        self.defs.get(&id)
+    }
+
+    // true for every id a level is allowed to use
+    pub fn is_known(&self, id: i32) -> bool {
+        id == EMPTY_TILE || self.defs.contains_key(&id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tiles() {
+        let source = r#"{ "tiles": [
+            { "id": 1, "name": "stone", "texture": "stone.png", "collidable": true },
+            { "id": 7, "name": "lava", "texture": "lava.png", "frames": [[0, 0, 32, 32], [32, 0, 32, 32]], "damage": 2, "layer": 1 },
+            { "id": 9, "name": "ice", "texture": "tiles.png", "region": [64, 0, 32, 32], "friction": 0.25 }
+        ] }"#;
+        let registry = TileRegistry::parse(source).unwrap();
+
+        let stone = registry.get(1).unwrap();
+        assert_eq!(stone.name, "stone");
+        assert!(stone.collidable);
+        assert_eq!((stone.damage, stone.friction, stone.layer), (0, 1.0, 0));
+        assert_eq!(stone.clip(Duration::from_secs(1)), None);
+
+        let lava = registry.get(7).unwrap();
+        assert!(!lava.collidable);
+        assert_eq!((lava.damage, lava.layer), (2, 1));
+        assert_eq!(lava.clip(Duration::from_millis(300)), Some(Rectangle::new(32.0, 0.0, 32.0, 32.0)));
+
+        let ice = registry.get(9).unwrap();
+        assert_eq!(ice.friction, 0.25);
+        assert_eq!(ice.clip(Duration::from_secs(0)), Some(Rectangle::new(64.0, 0.0, 32.0, 32.0)));
+
+        assert_eq!(registry.defs().count(), 3);
+    }
+
+    #[test]
+    fn unknown_ids() {
+        let registry = TileRegistry::parse(r#"{ "tiles": [{ "id": 1, "name": "stone", "texture": "stone.png" }] }"#).unwrap();
+
+        assert!(registry.get(2).is_none());
+        assert!(!registry.is_known(2));
+        assert!(!registry.is_known(-1));
+
+        // the empty tile is allowed in levels but has nothing to draw
+        assert!(registry.get(EMPTY_TILE).is_none());
+        assert!(registry.is_known(EMPTY_TILE));
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let source = r#"{ "tiles": [
+            { "id": 3, "name": "stone", "texture": "stone.png" },
+            { "id": 3, "name": "grass", "texture": "grass.png" }
+        ] }"#;
+
+        assert!(matches!(
+            TileRegistry::parse(source),
+            Err(TileRegistryError::DuplicateId { id: 3, ref first, ref second }) if first == "stone" && second == "grass"
+        ));
+    }
+
+    #[test]
+    fn reserved_id() {
+        let source = r#"{ "tiles": [{ "id": 0, "name": "void", "texture": "void.png" }] }"#;
+
+        assert!(matches!(
+            TileRegistry::parse(source),
+            Err(TileRegistryError::ReservedId { ref name }) if name == "void"
+        ));
+    }
+}
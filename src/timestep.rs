@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use vek::Vec2;
+
+// The simulation always advances in steps of exactly 1/TICKS_PER_SECOND, no matter how
+// fast the game is being drawn. Tetra runs as many updates as needed to catch up, and
+// `draw` blends between the last two updates using `time::get_blend_factor`.
+pub const TICKS_PER_SECOND: f64 = 60.0;
+
+// seconds per tick, for turning per-second speeds into per-tick motion
+pub const DT: f32 = (1.0 / TICKS_PER_SECOND) as f32;
+
+pub fn tick_duration() -> Duration {
+    Duration::from_secs_f64(1.0 / TICKS_PER_SECOND)
+}
+
+// where to draw something that moved from `previous` to `current` during the last tick,
+// `alpha` of the way into the next one. Weighted this way round it gives exactly `previous`
+// at 0 and exactly `current` at 1, however far apart they are.
+pub fn interpolate(previous: Vec2<f32>, current: Vec2<f32>, alpha: f32) -> Vec2<f32> {
+    previous * (1.0 - alpha) + current * alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolate_is_exact_at_the_ends() {
+        let previous = Vec2::new(10.3, -4.7);
+        let current = Vec2::new(-81.9, 1234.5);
+
+        assert_eq!(interpolate(previous, current, 0.0), previous);
+        assert_eq!(interpolate(previous, current, 1.0), current);
+        assert_eq!(interpolate(previous, previous, 0.5), previous);
+
+        // far apart, where `previous + (current - previous) * alpha` loses the small one
+        let far = Vec2::new(1.0e7, -1.0e7);
+        let near = Vec2::new(0.001, 0.003);
+        assert_eq!(interpolate(far, near, 1.0), near);
+        assert_eq!(interpolate(near, far, 0.0), near);
+    }
+
+    #[test]
+    fn interpolate_blends_in_between() {
+        let halfway = interpolate(Vec2::new(0.0, 10.0), Vec2::new(10.0, 30.0), 0.5);
+        assert_eq!(halfway, Vec2::new(5.0, 20.0));
+    }
+}
@@ -1,7 +1,45 @@
-use tetra::math::Vec2;
-use tetra::graphics::Camera;
+use std::error;
+use std::fs;
+use std::io;
+use std::path::Path;
 
-use crate::game_structs::{Tile, PlayerAttackSphere};
+use serde::de::DeserializeOwned;
+use vek::Vec2;
+
+use crate::camera::Camera;
+use crate::error::Error;
+use crate::geometry::Rectangle;
+use crate::level::TILE_SIZE;
+use crate::physics::CollisionGrid;
+
+// Reads the file at `path` and hands its contents to `parse`. A file that can't be read and
+// one that doesn't parse both come back as a load error naming the file.
+pub fn load_asset<T, E, F>(path: &Path, parse: F) -> crate::Result<T>
+where
+    F: FnOnce(&str) -> Result<T, E>,
+    E: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    let source = fs::read_to_string(path).map_err(|reason| Error::FailedToLoadAsset {
+        reason,
+        path: path.to_path_buf(),
+    })?;
+
+    parse(&source).map_err(|error| Error::FailedToLoadAsset {
+        reason: io::Error::new(io::ErrorKind::InvalidData, error),
+        path: path.to_path_buf(),
+    })
+}
+
+// A path written in an asset file in `directory`. Relative paths are relative to that
+// file, not to wherever the game was started from.
+pub fn asset_path(directory: &Path, path: &str) -> String {
+    directory.join(path).to_string_lossy().into_owned()
+}
+
+// Loads a JSON file straight into `T`.
+pub fn load_json<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> crate::Result<T> {
+    load_asset(path.as_ref(), |source| serde_json::from_str(source))
+}
 
 pub fn collision(
     obj_one: Vec2<f32>,
@@ -11,41 +49,244 @@ pub fn collision(
     obj_two_width: f32,
     obj_two_height: f32
 ) -> bool {
-    // self.player.position.x < tile.position.x + (tile.texture.width() as f32) &&
-    // self.player.position.x + (48.0) > tile.position.x &&
-    // self.player.position.y < (tile.position.y + tile.texture.height() as f32) &&
-    // self.player.position.y + (48.0) > tile.position.y && tile.collidable == true
-
-    if obj_one.x < obj_two.x + obj_two_width &&
-       obj_one.x + obj_one_width > obj_two.x &&
-       obj_one.y < obj_two.y + obj_two_height &&
-       obj_one.y + obj_one_height > obj_two.y {
-        true
-    } else {
-        false
-    }
-}
-
-pub fn in_camera_viewport(camera: &Camera, tile: &Tile) -> bool {
-    if tile.position.x < camera.position.x + ((camera.viewport_width as f32) / 2.0) &&
-       tile.position.x > camera.position.x - (((camera.viewport_width as f32) / 2.0) + 32.0) &&
-       tile.position.y < camera.position.y + ((camera.viewport_height as f32) / 2.0) &&
-       tile.position.y > camera.position.y - (((camera.viewport_height as f32) / 2.0) + 32.0) {
-
-           true
-       } else {
-           false
-       }
-}
-
-pub fn in_camera_viewport_attack(camera: &Camera, attack: &PlayerAttackSphere) -> bool {
-    if attack.position.x < camera.position.x + ((camera.viewport_width as f32) / 2.0) &&
-       attack.position.x > camera.position.x - (((camera.viewport_width as f32) / 2.0) + 32.0) &&
-       attack.position.y < camera.position.y + ((camera.viewport_height as f32) / 2.0) &&
-       attack.position.y > camera.position.y - (((camera.viewport_height as f32) / 2.0) + 32.0) {
-
-           true
-       } else {
-           false
-       }
+    obj_one.x < obj_two.x + obj_two_width &&
+        obj_one.x + obj_one_width > obj_two.x &&
+        obj_one.y < obj_two.y + obj_two_height &&
+        obj_one.y + obj_one_height > obj_two.y
+}
+
+// the part of the world the camera can currently see, ignoring rotation
+pub fn camera_view(camera: &Camera) -> Rectangle {
+    let width = camera.viewport_width / camera.zoom;
+    let height = camera.viewport_height / camera.zoom;
+
+    Rectangle::new(
+        camera.position.x - width / 2.0,
+        camera.position.y - height / 2.0,
+        width,
+        height,
+    )
+}
+
+// whether any of the box at `position` (top left, world space) is on screen
+pub fn in_camera_view(camera: &Camera, position: Vec2<f32>, size: Vec2<f32>) -> bool {
+    let view = camera_view(camera);
+
+    collision(position, Vec2::new(view.x, view.y), size.x, size.y, view.width, view.height)
+}
+
+// Breaks `text` into lines of at most `columns` characters, between words where it can.
+// Made for monospace fonts, where every character is as wide as the next. Line breaks in
+// the text are kept and words longer than a line are split.
+pub fn wrap_text(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut length = 0;
+
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+
+            if length > 0 && length + 1 + word.len() > columns {
+                lines.push(std::mem::take(&mut line));
+                length = 0;
+            }
+
+            // only ever at the start of a line, anything longer than a line didn't fit above
+            while word.len() > columns {
+                lines.push(word.drain(..columns).collect());
+            }
+
+            if length > 0 {
+                line.push(' ');
+                length += 1;
+            }
+
+            length += word.len();
+            line.extend(word);
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Where a ray ran into a collidable tile. `normal` points out of the face it hit, and is
+// zero when the ray started inside the tile.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RayHit {
+    pub cell: (i32, i32),
+    pub position: Vec2<f32>,
+    pub distance: f32,
+    pub normal: Vec2<f32>,
+}
+
+// Casts a ray from `origin` along `direction` (any length) through the tile grid and
+// returns the first collidable tile within `max_distance` pixels. Steps from cell border
+// to cell border (DDA), so it only visits the cells the ray passes through.
+pub fn raycast(grid: &CollisionGrid, origin: Vec2<f32>, direction: Vec2<f32>, max_distance: f32) -> Option<RayHit> {
+    let mut cell = ((origin.x / TILE_SIZE).floor() as i32, (origin.y / TILE_SIZE).floor() as i32);
+
+    if grid.is_solid(cell.0, cell.1) {
+        return Some(RayHit {
+            cell,
+            position: origin,
+            distance: 0.0,
+            normal: Vec2::zero(),
+        });
+    }
+
+    let length = direction.magnitude();
+    if length == 0.0 {
+        return None;
+    }
+    let direction = direction / length;
+
+    // how far along the ray the next vertical and horizontal cell borders are, and how
+    // far apart borders are along the ray
+    let axis = |origin: f32, direction: f32, cell: i32| -> (i32, f32, f32) {
+        if direction > 0.0 {
+            (1, ((cell + 1) as f32 * TILE_SIZE - origin) / direction, TILE_SIZE / direction)
+        } else if direction < 0.0 {
+            (-1, (cell as f32 * TILE_SIZE - origin) / direction, -TILE_SIZE / direction)
+        } else {
+            (0, f32::INFINITY, f32::INFINITY)
+        }
+    };
+    let (step_x, mut next_x, delta_x) = axis(origin.x, direction.x, cell.0);
+    let (step_y, mut next_y, delta_y) = axis(origin.y, direction.y, cell.1);
+
+    loop {
+        let (distance, normal) = if next_x < next_y {
+            cell.0 += step_x;
+            next_x += delta_x;
+            (next_x - delta_x, Vec2::new(-step_x as f32, 0.0))
+        } else {
+            cell.1 += step_y;
+            next_y += delta_y;
+            (next_y - delta_y, Vec2::new(0.0, -step_y as f32))
+        };
+
+        if distance > max_distance {
+            return None;
+        }
+
+        // nothing is solid outside the map, so a ray that has left it never hits anything
+        let leaving_x = (cell.0 < 0 && step_x < 0) || (cell.0 >= grid.width() && step_x > 0);
+        let leaving_y = (cell.1 < 0 && step_y < 0) || (cell.1 >= grid.height() && step_y > 0);
+        if leaving_x || leaving_y {
+            return None;
+        }
+
+        if grid.is_solid(cell.0, cell.1) {
+            return Some(RayHit {
+                cell,
+                position: origin + direction * distance,
+                distance,
+                normal,
+            });
+        }
+    }
+}
+
+// Whether a straight line from `from` to `to` misses every collidable tile.
+pub fn line_of_sight(grid: &CollisionGrid, from: Vec2<f32>, to: Vec2<f32>) -> bool {
+    let distance = from.distance(to);
+
+    raycast(grid, from, to - from, distance).is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn center(x: i32, y: i32) -> Vec2<f32> {
+        Vec2::new((x as f32 + 0.5) * TILE_SIZE, (y as f32 + 0.5) * TILE_SIZE)
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let grid = CollisionGrid::from_rows(&[
+            "....#",
+            ".....",
+            ".....",
+            ".#...",
+        ]);
+
+        let right = raycast(&grid, center(0, 0), Vec2::new(1.0, 0.0), 1000.0).unwrap();
+        assert_eq!(right.cell, (4, 0));
+        assert_eq!(right.position, Vec2::new(4.0 * TILE_SIZE, 0.5 * TILE_SIZE));
+        assert_eq!(right.distance, 3.5 * TILE_SIZE);
+        assert_eq!(right.normal, Vec2::new(-1.0, 0.0));
+
+        let up = raycast(&grid, center(4, 3), Vec2::new(0.0, -1.0), 1000.0).unwrap();
+        assert_eq!(up.cell, (4, 0));
+        assert_eq!(up.normal, Vec2::new(0.0, 1.0));
+
+        // nothing is solid off the map
+        assert_eq!(raycast(&grid, center(0, 1), Vec2::new(-1.0, 0.0), 1000.0), None);
+
+        let down = raycast(&grid, center(1, 0), Vec2::new(0.0, 5.0), 1000.0).unwrap();
+        assert_eq!(down.cell, (1, 3));
+        assert_eq!(down.normal, Vec2::new(0.0, -1.0));
+
+        // the length of `direction` doesn't matter, `max_distance` does
+        assert_eq!(raycast(&grid, center(0, 0), Vec2::new(1.0, 0.0), 3.0 * TILE_SIZE), None);
+    }
+
+    #[test]
+    fn rays_through_a_grid_corner() {
+        let one = CollisionGrid::from_rows(&[
+            "..",
+            ".#",
+        ]);
+        let hit = raycast(&one, center(0, 0), Vec2::new(1.0, 1.0), 1000.0).unwrap();
+        assert_eq!(hit.cell, (1, 1));
+        assert!(hit.position.distance(Vec2::new(TILE_SIZE, TILE_SIZE)) < 0.001);
+
+        // where two walls touch at their corners there is no gap to see through
+        let two = CollisionGrid::from_rows(&[
+            ".#",
+            "#.",
+        ]);
+        let hit = raycast(&two, center(0, 0), Vec2::new(1.0, 1.0), 1000.0).unwrap();
+        assert!(hit.cell == (1, 0) || hit.cell == (0, 1));
+        assert!((hit.distance - TILE_SIZE / 2.0 * 2.0_f32.sqrt()).abs() < 0.001);
+    }
+
+    #[test]
+    fn zero_length_rays() {
+        let grid = CollisionGrid::from_rows(&[
+            ".#",
+        ]);
+
+        assert_eq!(raycast(&grid, center(0, 0), Vec2::zero(), 1000.0), None);
+
+        // starting inside a wall hits it straight away, whatever the direction
+        let inside = raycast(&grid, center(1, 0), Vec2::zero(), 1000.0).unwrap();
+        assert_eq!(inside.cell, (1, 0));
+        assert_eq!(inside.distance, 0.0);
+        assert_eq!(inside.normal, Vec2::zero());
+
+        assert!(line_of_sight(&grid, center(0, 0), center(0, 0)));
+    }
+
+    #[test]
+    fn line_of_sight_is_blocked_by_walls() {
+        let grid = CollisionGrid::from_rows(&[
+            ".....",
+            "..#..",
+            ".....",
+        ]);
+
+        assert!(!line_of_sight(&grid, center(0, 1), center(4, 1)));
+        assert!(line_of_sight(&grid, center(0, 0), center(4, 0)));
+        assert!(line_of_sight(&grid, center(0, 2), center(4, 2)));
+        assert!(!line_of_sight(&grid, center(1, 0), center(3, 2)));
+        // stopping short of the wall
+        assert!(line_of_sight(&grid, center(0, 1), center(1, 1)));
+    }
 }
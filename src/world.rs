@@ -0,0 +1,421 @@
+use std::path::Path;
+use std::time::Duration;
+
+use vek::Vec2;
+
+use crate::animation::{AnimationState, Animations};
+use crate::arena::Arena;
+use crate::camera::{Camera, CameraConfig, CameraState};
+use crate::components::{
+    Ai, Checkpoint, Collider, ContactDamage, Defense, Health, HurtResponse, Interaction, PlayerControl, Respawn, Projectile, Sprite, Team, Transform,
+    Velocity, PHYSICAL,
+};
+use crate::controls::Input;
+use crate::dialogue::{Conversation, Dialogues, Story};
+use crate::direction::Direction;
+use crate::ecs::{Entity, EntityInfo, Storage};
+use crate::game_structs::{DialogueBox, DialogueBoxConfig, HelpMenu, Tile};
+use crate::geometry::Rectangle;
+use crate::level::{Level, TILE_SIZE};
+use crate::movement::MovementConfig;
+use crate::pathfinding::{PathSettings, Pathfinder};
+use crate::physics::CollisionGrid;
+use crate::prefabs::{PrefabError, Prefabs};
+use crate::systems;
+use crate::tilemap::tiles_from_level;
+use crate::tiles::TileRegistry;
+use crate::timestep::tick_duration;
+use crate::util::{camera_view, in_camera_view};
+
+// Everything the game simulates. `update` advances it by one tick of `timestep::DT`
+// seconds, reading only the `Input` it is given, so it runs the same with or without a
+// window.
+//
+// Game objects are entities: a handle into `entities` plus whichever components they
+// were spawned with from their prefab.
+pub struct World {
+    pub entities: Arena<EntityInfo>,
+    pub transforms: Storage<Transform>,
+    pub velocities: Storage<Velocity>,
+    pub colliders: Storage<Collider>,
+    pub sprites: Storage<Sprite>,
+    pub healths: Storage<Health>,
+    pub defenses: Storage<Defense>,
+    pub hurt_responses: Storage<HurtResponse>,
+    pub respawns: Storage<Respawn>,
+    pub checkpoints: Storage<Checkpoint>,
+    pub teams: Storage<Team>,
+    pub ais: Storage<Ai>,
+    pub interactions: Storage<Interaction>,
+    pub projectiles: Storage<Projectile>,
+    pub contact_damages: Storage<ContactDamage>,
+    pub player_controls: Storage<PlayerControl>,
+    pub player: Entity,
+    pub prefabs: Prefabs,
+    pub animations: Animations,
+    pub tiles: Vec<Tile>,
+    pub tile_registry: TileRegistry,
+    pub collision_grid: CollisionGrid,
+    pub pathfinder: Pathfinder,
+    pub movement_config: MovementConfig,
+    pub camera: Camera,
+    pub camera_previous_position: Vec2<f32>,
+    pub camera_config: CameraConfig,
+    pub camera_state: CameraState,
+    // the whole level in world pixels, the camera stays inside it
+    pub level_bounds: Rectangle,
+    pub help_menu: HelpMenu,
+    pub dialogue_box: DialogueBox,
+    pub dialogues: Dialogues,
+    pub story: Story,
+    // the conversation the player is in, the game waits while it goes on
+    pub conversation: Option<Conversation>,
+    // how long the world has been running, drives tile animations
+    pub elapsed: Duration,
+}
+
+// Everything a world is built from besides the level itself.
+pub struct WorldConfig {
+    pub tile_registry: TileRegistry,
+    pub prefabs: Prefabs,
+    pub animations: Animations,
+    pub dialogues: Dialogues,
+    pub movement: MovementConfig,
+    pub camera: CameraConfig,
+    pub dialogue_box: DialogueBoxConfig,
+    // the size of the window the world is shown in, in pixels
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl WorldConfig {
+    // Loads the game's data files from `directory`, usually `./resources`.
+    pub fn load<P: AsRef<Path>>(directory: P, viewport_width: f32, viewport_height: f32) -> crate::Result<WorldConfig> {
+        let directory = directory.as_ref();
+
+        Ok(WorldConfig {
+            tile_registry: TileRegistry::load(directory.join("tiles.json"))?,
+            prefabs: Prefabs::load(directory.join("prefabs.json"))?,
+            animations: Animations::load(directory.join("animations.json"))?,
+            dialogues: Dialogues::load(directory.join("dialogue"))?,
+            movement: MovementConfig::load(directory.join("movement.json"))?,
+            camera: CameraConfig::load(directory.join("camera.json"))?,
+            dialogue_box: DialogueBoxConfig::load(directory.join("dialogue_box.json"))?,
+            viewport_width,
+            viewport_height,
+        })
+    }
+}
+
+impl World {
+    pub fn new(level: &Level, config: WorldConfig) -> Result<World, PrefabError> {
+        let WorldConfig {
+            tile_registry,
+            prefabs,
+            animations,
+            dialogues,
+            movement: movement_config,
+            camera: camera_config,
+            dialogue_box: dialogue_box_config,
+            viewport_width,
+            viewport_height,
+        } = config;
+
+        let help_menu_visible = false;
+        let help_menu_text = String::new();
+
+        let dialogue_box_visible = false;
+
+        let tiles = tiles_from_level(level, &tile_registry);
+        let collision_grid = CollisionGrid::from_tiles(&tiles, level.width, level.height);
+        let pathfinder = Pathfinder::new(&collision_grid, PathSettings::default());
+
+        if prefabs.get("player").is_none() {
+            return Err(PrefabError::UnknownPrefab {
+                name: String::from("player"),
+                used_by: String::from("the level's player spawn"),
+            });
+        }
+
+        // the player is the first entity, its components are added once the world exists
+        let mut entities: Arena<EntityInfo> = Arena::new();
+        let player = entities.insert(EntityInfo { prefab: String::from("player") });
+
+        let mut world = World {
+            entities,
+            transforms: Storage::new(),
+            velocities: Storage::new(),
+            colliders: Storage::new(),
+            sprites: Storage::new(),
+            healths: Storage::new(),
+            defenses: Storage::new(),
+            hurt_responses: Storage::new(),
+            respawns: Storage::new(),
+            checkpoints: Storage::new(),
+            teams: Storage::new(),
+            ais: Storage::new(),
+            interactions: Storage::new(),
+            projectiles: Storage::new(),
+            contact_damages: Storage::new(),
+            player_controls: Storage::new(),
+            player,
+            prefabs,
+            animations,
+            tiles,
+            tile_registry,
+            collision_grid,
+            pathfinder,
+            movement_config,
+            camera: Camera::new(viewport_width, viewport_height),
+            camera_previous_position: Vec2::zero(),
+            camera_config,
+            camera_state: CameraState::new(Vec2::zero()),
+            level_bounds: Rectangle::new(0.0, 0.0, level.width as f32 * TILE_SIZE, level.height as f32 * TILE_SIZE),
+            help_menu: HelpMenu::new(
+                help_menu_visible,
+                help_menu_text,
+            ),
+            dialogue_box: DialogueBox::new(
+                dialogue_box_visible,
+                dialogue_box_config,
+            ),
+            dialogues,
+            story: Story::new(),
+            conversation: None,
+            elapsed: Duration::from_secs(0),
+        };
+
+        world.add_components(player, level.player_spawn);
+
+        for spawn in &level.spawns {
+            world.spawn(&spawn.prefab, spawn.position).ok_or_else(|| PrefabError::UnknownPrefab {
+                name: spawn.prefab.clone(),
+                used_by: String::from("a level spawn"),
+            })?;
+        }
+
+        for (entity, interaction) in world.interactions.iter() {
+            if world.dialogues.get(&interaction.dialogue).is_none() {
+                return Err(PrefabError::UnknownDialogue {
+                    name: interaction.dialogue.clone(),
+                    prefab: world.entities.get(entity).map_or(String::new(), |info| info.prefab.clone()),
+                });
+            }
+        }
+
+        systems::snap_camera(&mut world);
+        world.camera_previous_position = world.camera.position;
+
+        Ok(world)
+    }
+
+    // Creates an entity from the named prefab, None if there is no such prefab.
+    pub fn spawn(&mut self, name: &str, position: Vec2<f32>) -> Option<Entity> {
+        self.prefabs.get(name)?;
+        let entity = self.entities.insert(EntityInfo { prefab: name.to_string() });
+        self.add_components(entity, position);
+
+        Some(entity)
+    }
+
+    // Gives a new entity the components of the prefab it was created from.
+    fn add_components(&mut self, entity: Entity, position: Vec2<f32>) {
+        let prefabs = &self.prefabs;
+        let prefab = match self.entities.get(entity).and_then(|info| prefabs.get(&info.prefab)) {
+            Some(prefab) => prefab,
+            None => return,
+        };
+
+        self.transforms.insert(entity, Transform::new(position));
+
+        if prefab.ai.is_some() || prefab.player_control.is_some() || prefab.projectile.is_some() {
+            self.velocities.insert(entity, Velocity(Vec2::zero()));
+        }
+
+        if let Some(collider) = &prefab.collider {
+            self.colliders.insert(entity, Collider {
+                size: Vec2::new(collider.size[0], collider.size[1]),
+                walls: collider.walls,
+                blocked_x: false,
+                blocked_y: false,
+            });
+        }
+
+        if let Some(sprite) = &prefab.sprite {
+            self.sprites.insert(entity, Sprite {
+                name: sprite.name.clone(),
+                depth: sprite.depth,
+                visible: true,
+                state: AnimationState::Idle,
+                direction: Direction::Down,
+                animation_time: Duration::from_secs(0),
+                trigger: None,
+            });
+        }
+
+        if let Some(health) = prefab.health {
+            self.healths.insert(entity, Health {
+                current: health,
+                max: health,
+            });
+        }
+
+        if let Some(defense) = &prefab.defense {
+            self.defenses.insert(entity, Defense {
+                armour: defense.armour,
+                resistances: defense.resistances.clone(),
+            });
+        }
+
+        if let Some(hurt) = &prefab.hurt {
+            self.hurt_responses.insert(entity, HurtResponse {
+                invulnerable_for: Duration::from_millis(hurt.invulnerable_ms),
+                knockback: hurt.knockback,
+                knockback_for: Duration::from_millis(hurt.knockback_ms),
+                invulnerable: Duration::from_secs(0),
+                knocked_back: Duration::from_secs(0),
+            });
+        }
+
+        if let Some(respawn) = &prefab.respawn {
+            self.respawns.insert(entity, Respawn {
+                point: position,
+                delay: Duration::from_millis(respawn.delay_ms),
+                lives: respawn.lives,
+                dead_for: None,
+            });
+        }
+
+        if prefab.checkpoint {
+            self.checkpoints.insert(entity, Checkpoint { reached: false });
+        }
+
+        if let Some(team) = prefab.team {
+            self.teams.insert(entity, team);
+        }
+
+        if let Some(params) = prefab.ai {
+            self.ais.insert(entity, Ai::new(params, position));
+        }
+
+        if let Some(interaction) = &prefab.interaction {
+            self.interactions.insert(entity, Interaction {
+                prompt: interaction.prompt.clone(),
+                dialogue: interaction.dialogue.clone(),
+            });
+        }
+
+        if let Some(projectile) = &prefab.projectile {
+            self.projectiles.insert(entity, Projectile {
+                damage: projectile.damage,
+                kind: projectile.kind.clone(),
+            });
+        }
+
+        if let Some(damage) = prefab.contact_damage {
+            self.contact_damages.insert(entity, ContactDamage {
+                damage,
+                kind: String::from(PHYSICAL),
+            });
+        }
+
+        if let Some(control) = &prefab.player_control {
+            self.player_controls.insert(entity, PlayerControl {
+                facing: None,
+                prev_facing: Direction::Down,
+                attack: control.attack.clone(),
+                attack_speed: control.attack_speed,
+                max_attacks: control.max_attacks,
+            });
+        }
+    }
+
+    // Queues an entity to be removed at the end of the combat step, see `update`.
+    pub fn despawn(&mut self, entity: Entity) {
+        self.entities.despawn(entity);
+    }
+
+    pub fn apply_despawns(&mut self) {
+        for entity in self.entities.apply_despawns() {
+            self.transforms.remove(entity);
+            self.velocities.remove(entity);
+            self.colliders.remove(entity);
+            self.sprites.remove(entity);
+            self.healths.remove(entity);
+            self.defenses.remove(entity);
+            self.hurt_responses.remove(entity);
+            self.respawns.remove(entity);
+            self.checkpoints.remove(entity);
+            self.teams.remove(entity);
+            self.ais.remove(entity);
+            self.interactions.remove(entity);
+            self.projectiles.remove(entity);
+            self.contact_damages.remove(entity);
+            self.player_controls.remove(entity);
+        }
+    }
+
+    // Shakes the camera, `amount` between 0 and 1 adds up to at most full trauma.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.camera_state.trauma = (self.camera_state.trauma + amount).min(1.0);
+    }
+
+    // Waypoints for walking `entity` to `goal` around walls, top left corners in world
+    // pixels. For scripted movement, enemies find their own way in `systems::ai`.
+    pub fn path_to(&mut self, entity: Entity, goal: Vec2<f32>) -> Option<Vec<Vec2<f32>>> {
+        let position = self.transforms.get(entity)?.position;
+        let size = self.colliders.get(entity).map_or(Vec2::zero(), |collider| collider.size);
+
+        self.pathfinder.find_path(position, goal, size)
+    }
+
+    // the part of the world on screen this tick
+    pub fn visible_area(&self) -> Rectangle {
+        camera_view(&self.camera)
+    }
+
+    // Whether any of an entity's collider, or its position without one, is on screen.
+    // Only for what to bother with, gameplay like hits works the same off screen.
+    pub fn is_visible(&self, entity: Entity) -> bool {
+        let position = match self.transforms.get(entity) {
+            Some(transform) => transform.position,
+            None => return false,
+        };
+        let size = self.colliders.get(entity).map_or(Vec2::zero(), |collider| collider.size);
+
+        in_camera_view(&self.camera, position, size)
+    }
+
+    pub fn player_alive(&self) -> bool {
+        self.healths.get(self.player).is_none_or(|health| health.current > 0)
+    }
+
+    // the player is dead and won't come back
+    pub fn game_over(&self) -> bool {
+        !self.player_alive() && self.respawns.get(self.player).is_none_or(|respawn| respawn.dead_for.is_none())
+    }
+
+    pub fn update(&mut self, input: &Input) {
+        self.elapsed += tick_duration();
+
+        systems::remember_positions(self);
+        systems::follow_camera(self);
+        systems::interaction(self, input);
+        systems::checkpoints(self);
+        systems::cull_projectiles(self);
+        systems::combat(self);
+        systems::remove_dead(self);
+
+        // everything destroyed so far this tick is removed here, before anything moves
+        self.apply_despawns();
+
+        systems::recover(self);
+        systems::respawn(self);
+
+        systems::ai(self);
+        systems::player_control(self, input);
+        systems::movement(self);
+        systems::attack(self, input);
+        systems::animation(self);
+    }
+}
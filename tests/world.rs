@@ -0,0 +1,126 @@
+// Builds a `World` from the game's own resources and steps it, the same way the game does
+// but without a window.
+
+use std::fs;
+use std::path::Path;
+
+use hypoterra::controls::Input;
+use hypoterra::level::Level;
+use hypoterra::timestep::tick_duration;
+use hypoterra::world::{World, WorldConfig};
+use vek::Vec2;
+
+fn load_world() -> World {
+    let config = WorldConfig::load("./resources", 1280.0, 960.0).unwrap();
+    let level = Level::load("./resources/levels/level1.lvl", &config.tile_registry).unwrap();
+
+    World::new(&level, config).unwrap()
+}
+
+fn player_position(world: &World) -> Vec2<f32> {
+    world.transforms.get(world.player).unwrap().position
+}
+
+#[test]
+fn the_player_is_the_entity_spawned_for_it() {
+    let world = load_world();
+
+    assert!(world.entities.contains(world.player));
+    assert_eq!(world.entities.get(world.player).unwrap().prefab, "player");
+    assert!(world.player_controls.get(world.player).is_some());
+}
+
+#[test]
+fn stepping_moves_the_player_and_the_clock() {
+    let mut world = load_world();
+    let start = player_position(&world);
+    let input = Input {
+        movement: Vec2::new(1.0, 0.0),
+        ..Input::default()
+    };
+
+    for _ in 0..30 {
+        world.update(&input);
+    }
+
+    assert_eq!(world.elapsed, tick_duration() * 30);
+    assert!(player_position(&world).x > start.x);
+    assert!(world.player_alive());
+    assert!(!world.game_over());
+}
+
+#[test]
+fn standing_still_keeps_the_player_in_place() {
+    let mut world = load_world();
+    let start = player_position(&world);
+
+    for _ in 0..30 {
+        world.update(&Input::default());
+    }
+
+    assert_eq!(player_position(&world), start);
+}
+
+// Every entity's position after `ticks` updates of a scripted run, in spawn order.
+fn scripted_run(ticks: usize) -> Vec<Vec2<f32>> {
+    let mut world = load_world();
+
+    for tick in 0..ticks {
+        let angle = tick as f32 * 0.05;
+        world.update(&Input {
+            movement: Vec2::new(angle.cos(), angle.sin()),
+            attack: tick.is_multiple_of(20),
+            ..Input::default()
+        });
+    }
+
+    world.transforms.iter().map(|(_, transform)| transform.position).collect()
+}
+
+#[test]
+fn the_same_inputs_give_the_same_world() {
+    let first = scripted_run(300);
+    let second = scripted_run(300);
+
+    assert!(!first.is_empty());
+    assert_eq!(first, second);
+}
+
+// Copies `from` into `to`, subdirectories included.
+fn copy_directory(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+
+    for entry in fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+
+        if path.is_dir() {
+            copy_directory(&path, &target);
+        } else {
+            fs::copy(&path, &target).unwrap();
+        }
+    }
+}
+
+#[test]
+fn resources_load_from_any_directory() {
+    let directory = std::env::temp_dir().join(format!("hypoterra-resources-{}", std::process::id()));
+    copy_directory(Path::new("./resources"), &directory);
+
+    let config = WorldConfig::load(&directory, 1280.0, 960.0);
+    let level = Level::load(directory.join("levels/level1.lvl"), &config.as_ref().unwrap().tile_registry);
+    fs::remove_dir_all(&directory).unwrap();
+    let config = config.unwrap();
+
+    // the paths inside the data files point into the copy, not at ./resources
+    for def in config.tile_registry.defs() {
+        assert!(Path::new(&def.texture).starts_with(&directory), "{}", def.texture);
+    }
+    for set in config.animations.sets() {
+        for clip in set.clips() {
+            assert!(Path::new(&clip.texture).starts_with(&directory), "{}", clip.texture);
+        }
+    }
+
+    assert!(World::new(&level.unwrap(), config).is_ok());
+}